@@ -0,0 +1,106 @@
+//! Times edits on the text storage at the sizes it has to cope with: a
+//! 64 MB file of short lines and a multi-megabyte single-line JSON file.
+//! Run with `cargo bench`.
+
+use hecto::text::{BlockList, Text};
+use hecto::{Document, Position};
+use std::env;
+use std::fs;
+use std::hint::black_box;
+use std::process;
+use std::time::{Duration, Instant};
+
+// The largest file the editor opens for editing rather than read-only.
+const FILE_BYTES: usize = 64 * 1024 * 1024;
+const LINE_BYTES: usize = 4 * 1024 * 1024;
+const EDITS: usize = 1_000;
+
+fn main() {
+    let line = json_line(LINE_BYTES);
+    let mut text = Text::from(line.as_str());
+    let middle = text.len() / 2;
+    report("single line: insert in the middle", || {
+        text.insert(middle, "x");
+    });
+    report("single line: delete in the middle", || {
+        text.remove(middle, middle + 1);
+    });
+    report("single line: insert at the end", || {
+        text.insert(text.len(), "x");
+    });
+    report("single line: column of the middle", || {
+        black_box(text.columns_from_index(middle, 4).next());
+    });
+    report("single line: grapheme at the middle", || {
+        black_box(text.grapheme(middle));
+    });
+
+    let mut document = open_document(&line);
+    let middle = Position {
+        x: text.len() / 2,
+        y: 0,
+    };
+    report("single line: type with highlighting on", || {
+        document.insert(&middle, 'x');
+    });
+
+    let mut lines: BlockList<Text> = file_lines(FILE_BYTES).collect();
+    let middle = lines.len() / 2;
+    report("64 MB file: split a line in the middle", || {
+        let rest = lines[middle].split_off(10);
+        lines.insert(middle + 1, rest);
+    });
+    report("64 MB file: join a line in the middle", || {
+        let next = lines.remove(middle + 1);
+        lines[middle].append(next);
+    });
+    report("64 MB file: look up a line", || {
+        black_box(lines.get(middle));
+    });
+}
+
+// Runs `edit` `EDITS` times and prints how long each took on average.
+fn report<F: FnMut()>(name: &str, mut edit: F) {
+    let start = Instant::now();
+    for _ in 0..EDITS {
+        edit();
+    }
+    let each = start.elapsed() / EDITS as u32;
+    println!("{:<45} {:>10}", name, format_duration(each));
+}
+
+fn format_duration(duration: Duration) -> String {
+    if duration.as_micros() >= 1_000 {
+        format!("{:.2} ms", duration.as_secs_f64() * 1e3)
+    } else {
+        format!("{:.2} µs", duration.as_secs_f64() * 1e6)
+    }
+}
+
+// Opens `line` as a Rust file, so its strings and numbers are highlighted,
+// and highlights it as if it were on screen.
+fn open_document(line: &str) -> Document {
+    let path = env::temp_dir().join(format!("hecto-bench-{}.rs", process::id()));
+    fs::write(&path, line).expect("write the benchmark file");
+    let mut document = Document::open(&path.to_string_lossy()).expect("open the benchmark file");
+    while document.load_more().expect("load the benchmark file") {}
+    let _ = fs::remove_file(&path);
+    document.hightlight_through(0);
+    document
+}
+
+fn json_line(bytes: usize) -> String {
+    let mut line = String::from("[");
+    let mut index = 0;
+    while line.len() < bytes {
+        line.push_str(&format!("{{\"id\":{},\"name\":\"item {}\",\"tags\":[\"a\",\"é\"]}},", index, index));
+        index += 1;
+    }
+    line.push(']');
+    line
+}
+
+fn file_lines(bytes: usize) -> impl Iterator<Item = Text> {
+    let line = "    let value = compute(first, second) + offset; // note";
+    (0..bytes / (line.len() + 1)).map(move |_| Text::from(line))
+}
@@ -0,0 +1,177 @@
+use crate::terminal::Backend;
+use std::env;
+use std::io::{Error, ErrorKind, Write};
+use std::process::{Command, Stdio};
+
+const KILL_RING_SIZE: usize = 16;
+const BASE64_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Recently cut or copied text, newest first. Yanking pastes the newest kill
+/// and each rotation after a yank steps to an older one.
+#[derive(Default)]
+pub struct KillRing {
+    kills: Vec<String>,
+    yank_index: usize,
+}
+
+impl KillRing {
+    pub fn kill(&mut self, text: String) {
+        if text.is_empty() {
+            return;
+        }
+        self.kills.insert(0, text);
+        self.kills.truncate(KILL_RING_SIZE);
+        self.yank_index = 0;
+    }
+
+    /// Adds to the newest kill, so repeated line cuts paste back together.
+    pub fn append(&mut self, text: &str) {
+        match self.kills.first_mut() {
+            Some(last) => last.push_str(text),
+            None => self.kill(text.to_string()),
+        }
+    }
+
+    pub fn newest(&self) -> Option<&str> {
+        self.kills.first().map(String::as_str)
+    }
+
+    pub fn yank(&mut self) -> Option<&str> {
+        self.yank_index = 0;
+        self.kills.first().map(String::as_str)
+    }
+
+    pub fn rotate(&mut self) -> Option<&str> {
+        if self.kills.is_empty() {
+            return None;
+        }
+        self.yank_index = (self.yank_index + 1) % self.kills.len();
+        self.kills.get(self.yank_index).map(String::as_str)
+    }
+}
+
+/// Where copied text is sent outside the editor. Chosen with the
+/// `HECTO_CLIPBOARD` environment variable: unset or `osc52` writes an OSC 52
+/// escape sequence to the terminal, `none` turns it off, and anything else is
+/// run as a command (such as `xclip -selection clipboard` or `wl-copy`) with
+/// the text on its stdin.
+pub enum SystemClipboard {
+    Osc52,
+    Command(Vec<String>),
+    Disabled,
+}
+
+impl SystemClipboard {
+    pub fn from_env() -> Self {
+        match env::var("HECTO_CLIPBOARD") {
+            Err(_) => SystemClipboard::Osc52,
+            Ok(value) => match value.trim() {
+                "" | "osc52" => SystemClipboard::Osc52,
+                "none" => SystemClipboard::Disabled,
+                command => SystemClipboard::Command(
+                    command.split_whitespace().map(String::from).collect(),
+                ),
+            },
+        }
+    }
+
+    pub fn copy(&self, text: &str, terminal: &mut dyn Backend) -> Result<(), Error> {
+        match self {
+            SystemClipboard::Osc52 => {
+                terminal.set_clipboard(&base64(text.as_bytes()));
+                terminal.flash()
+            }
+            SystemClipboard::Command(command) => {
+                let mut child = Command::new(&command[0])
+                    .args(&command[1..])
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .spawn()?;
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(text.as_bytes())?;
+                }
+                let status = child.wait()?;
+                if !status.success() {
+                    return Err(Error::new(
+                        ErrorKind::Other,
+                        format!("{} exited with {}", command[0], status),
+                    ));
+                }
+                Ok(())
+            }
+            SystemClipboard::Disabled => Ok(()),
+        }
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    let mut result = String::new();
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let indices = [
+            b[0] >> 2,
+            (b[0] & 0b11) << 4 | b[1] >> 4,
+            (b[1] & 0b1111) << 2 | b[2] >> 6,
+            b[2] & 0b11_1111,
+        ];
+        for (index, value) in indices.iter().enumerate() {
+            if index <= chunk.len() {
+                result.push(BASE64_ALPHABET[*value as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::virtual_screen::VirtualScreen;
+    use std::{fs, process};
+
+    #[test]
+    fn base64_matches_rfc_4648() {
+        // The test vectors from section 10, covering both kinds of padding.
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (text, encoded) in vectors {
+            assert_eq!(base64(text.as_bytes()), encoded, "encoding {:?}", text);
+        }
+        assert_eq!(base64(&[0xfb, 0xff, 0xbf]), "+/+/");
+    }
+
+    #[test]
+    fn command_gets_the_text_on_stdin() {
+        let path = env::temp_dir().join(format!("hecto-{}-clipboard", process::id()));
+        let command = format!("cat > '{}'", path.display());
+        let clipboard = SystemClipboard::Command(vec!["sh".into(), "-c".into(), command]);
+        let mut screen = VirtualScreen::new(20, 5);
+        clipboard.copy("copied\ntext ✓", &mut screen).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "copied\ntext ✓");
+        assert_eq!(screen.clipboard(), None);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn command_failures_are_errors() {
+        let mut screen = VirtualScreen::new(20, 5);
+        let failing = SystemClipboard::Command(vec!["false".into()]);
+        assert!(failing.copy("text", &mut screen).is_err());
+        let missing = SystemClipboard::Command(vec!["hecto-no-such-command".into()]);
+        assert!(missing.copy("text", &mut screen).is_err());
+    }
+}
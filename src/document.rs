@@ -1,16 +1,168 @@
+use std::cmp;
+use std::collections::hash_map::DefaultHasher;
+use std::env;
 use std::fs;
-use std::io::Error;
-use std::io::Write;
+use std::hash::Hasher;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::SystemTime;
+use encoding_rs::{Decoder, DecoderResult, Encoding};
+use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
 
+use crate::encoding::{self, FileEncoding};
+use crate::filetype::FileType;
+use crate::hightlighting;
+use crate::history::{History, Operation};
 use crate::Position;
 use crate::Row;
 use crate::SearchDirection;
+use crate::text::BlockList;
+
+const MAX_SYMLINKS: usize = 40;
+const LOAD_PIECE_BYTES: usize = 1024 * 1024;
+// The most bytes an invalid sequence and those read past it can span.
+const MAX_INVALID_BYTES: usize = 6;
+const DEFAULT_LARGE_FILE_MB: u64 = 64;
+
+/// What separates rows in the file on disk.
+#[derive(Default, PartialEq, Copy, Clone)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+/// A row split in two or joined onto the one before it, which moves the
+/// text after it, so that other views of the document can follow that text.
+#[derive(Clone)]
+pub enum RowChange {
+    /// The row at `y` was split at `x`.
+    Split(Position),
+    /// The row after `y` was joined onto its end, which was at `x`.
+    Join(Position),
+}
+
+impl RowChange {
+    /// Where the text that was at `position` is now.
+    pub fn follow(&self, position: &Position) -> Position {
+        let Position { x, y } = *position;
+        match self {
+            RowChange::Split(at) if y == at.y && x >= at.x => Position {
+                x: x - at.x,
+                y: y + 1,
+            },
+            RowChange::Join(at) if y == at.y + 1 => Position { x: x + at.x, y: at.y },
+            _ => Position {
+                x,
+                y: self.follow_row(y),
+            },
+        }
+    }
+
+    /// Where row `y` is now, for a row that wasn't split or joined.
+    pub fn follow_row(&self, y: usize) -> usize {
+        match self {
+            RowChange::Split(at) if y > at.y => y + 1,
+            RowChange::Join(at) if y > at.y => y - 1,
+            _ => y,
+        }
+    }
+}
+
+// The file as it was when last read or written, to tell whether something
+// else has changed it since.
+struct DiskState {
+    modified: Option<SystemTime>,
+    len: u64,
+    hash: u64,
+}
+
+impl DiskState {
+    fn new(metadata: &fs::Metadata, bytes: &[u8]) -> Self {
+        Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            hash: hash_bytes(bytes),
+        }
+    }
+}
+
+// Reads a file a piece at a time, so a large one can be shown and scrolled
+// through before all of it has been read.
+struct Loader {
+    file: fs::File,
+    modified: Option<SystemTime>,
+    len: u64,
+    read: u64,
+    hasher: DefaultHasher,
+    // The encoding the user chose, rather than one guessed from the file.
+    chosen: Option<&'static Encoding>,
+    // Made once the first piece shows what the encoding is.
+    decoder: Option<Decoder>,
+    // The end of the piece before, where an invalid sequence the decoder
+    // only finds in this piece may have started.
+    tail: Vec<u8>,
+    // The start of a line whose end hasn't been read yet.
+    partial: String,
+}
 
 #[derive(Default)]
 pub struct Document {
-    rows: Vec<Row>,
+    rows: BlockList<Row>,
     pub filename: Option<String>,
     dirty: bool,
+    history: History,
+    file_type: FileType,
+    line_ending: LineEnding,
+    // Whether lines end in both styles, in which case each `\r` is kept in
+    // its row and the file is read as LF so saving writes them back as is.
+    mixed_line_endings: bool,
+    encoding: FileEncoding,
+    // Set when the user picks the encoding, so reloading keeps it.
+    chosen_encoding: Option<&'static Encoding>,
+    // Whether some bytes weren't valid in the encoding, and so are kept as
+    // raw byte characters.
+    raw_bytes: bool,
+    // The file's bytes, while it is shown and edited in hex rather than
+    // as text.
+    hex: Option<Vec<u8>>,
+    // Whether the file ended without a newline, so saving leaves it off too.
+    missing_final_newline: bool,
+    disk_state: Option<DiskState>,
+    loader: Option<Loader>,
+    // Over the size limit, so it can't be changed and isn't highlighted.
+    large: bool,
+    // Whether only the start of the file was read, as a binary over the
+    // size limit is shown in hex no further than the limit.
+    truncated: bool,
+    // Whether it can't be changed, such as when it couldn't all be read.
+    read_only: bool,
+    // How many rows from the top have been highlighted; the rest are done
+    // as they come into view.
+    hightlighted: usize,
+    // What to highlight matches of, while searching.
+    word: Option<String>,
+    // Rows split or joined since the editor last took them.
+    row_changes: Vec<RowChange>,
 }
 
 impl Document {
@@ -19,21 +171,311 @@ impl Document {
     //     rows.push(Row::from("Hello,world"));
     //     Self { rows }
     // }
+    /// Opens `filename`, reading only its first piece; a larger file is
+    /// read the rest of the way by `load_more`. Over the size limit, the
+    /// document is read-only and isn't highlighted, and a binary file is
+    /// only read up to the limit.
     pub fn open(filename: &str) -> Result<Self, Error> {
-        let contents = fs::read_to_string(filename)?;
-        let mut rows = Vec::new();
-        for item in contents.lines() {
-            // print!("{}", item);
-            // rows.push(Row::from(item));
-            let mut row = Row::from(item);
-            row.hightlight(None);
-            rows.push(row);
-        }
-        Ok(Self {
-            rows,
+        Self::open_with_encoding(filename, None)
+    }
+
+    /// Opens `filename` as text in `encoding`, or in the encoding its first
+    /// piece looks like if that is `None`.
+    pub fn open_with_encoding(filename: &str, encoding: Option<&'static Encoding>) -> Result<Self, Error> {
+        let file = fs::File::open(filename)?;
+        let metadata = file.metadata()?;
+        let mut document = Self {
             filename: Some(filename.to_string()),
-            dirty: false,
-        })
+            file_type: FileType::from(filename),
+            large: metadata.len() > large_file_size(),
+            chosen_encoding: encoding,
+            loader: Some(Loader {
+                file,
+                modified: metadata.modified().ok(),
+                len: metadata.len(),
+                read: 0,
+                hasher: DefaultHasher::new(),
+                chosen: encoding,
+                decoder: None,
+                tail: Vec::new(),
+                partial: String::new(),
+            }),
+            ..Self::default()
+        };
+        document.load_more()?;
+        Ok(document)
+    }
+
+    /// Reads the next piece of a file that is still loading, returning
+    /// whether there is more to come. The document can't be changed until
+    /// it has all been read, nor at all if reading it fails.
+    pub fn load_more(&mut self) -> Result<bool, Error> {
+        let mut loader = match self.loader.take() {
+            Some(loader) => loader,
+            None => return Ok(false),
+        };
+        let result = self.load_piece(&mut loader);
+        match result {
+            Ok(true) => self.loader = Some(loader),
+            Ok(false) => (),
+            Err(_) => self.read_only = true,
+        }
+        result
+    }
+
+    fn load_piece(&mut self, loader: &mut Loader) -> Result<bool, Error> {
+        let mut piece = vec![0; LOAD_PIECE_BYTES];
+        let read = loader.file.read(&mut piece)?;
+        let bytes = &piece[..read];
+        loader.hasher.write(bytes);
+        let first = loader.read == 0;
+        loader.read = loader.read.saturating_add(read as u64);
+        let mut last = read == 0 || loader.read >= loader.len;
+        if first {
+            self.encoding = match loader.chosen {
+                Some(encoding) => FileEncoding::with_encoding(encoding, bytes),
+                None => FileEncoding::detect(bytes, last),
+            };
+            // Unless asked to read it as text, a binary file is shown in hex.
+            if loader.chosen.is_none() && self.encoding.is_binary(bytes) {
+                self.hex = Some(Vec::new());
+            }
+        }
+        if let Some(hex) = self.hex.as_mut() {
+            hex.extend_from_slice(bytes);
+            let limit = large_file_size();
+            if self.large && hex.len() as u64 >= limit {
+                hex.truncate(usize::try_from(limit).unwrap_or(usize::MAX));
+                self.truncated = true;
+                last = true;
+            }
+        } else {
+            let decoder = loader.decoder.get_or_insert_with(|| self.encoding.new_decoder());
+            self.raw_bytes |= decode(decoder, &loader.tail, bytes, &mut loader.partial, last);
+            self.push_lines(&mut loader.partial, last);
+            loader.tail = bytes[bytes.len().saturating_sub(MAX_INVALID_BYTES)..].to_vec();
+        }
+        if !last {
+            return Ok(true);
+        }
+        // A truncated file's hash is only of its start, so a change to its
+        // size or time is always taken as a change to it.
+        self.disk_state = Some(DiskState {
+            modified: loader.modified,
+            len: if self.truncated { loader.len } else { loader.read },
+            hash: loader.hasher.finish(),
+        });
+        Ok(false)
+    }
+
+    // Adds the complete lines at the start of `text` as rows, leaving the
+    // rest until more has been read. At the `last` of the file, what is
+    // left is a final line that had no newline.
+    fn push_lines(&mut self, text: &mut String, last: bool) {
+        let mut start = 0;
+        while let Some(len) = text[start..].find('\n') {
+            let line = &text[start..start + len];
+            // The first line decides the style, until a line doesn't match it.
+            if self.rows.is_empty() && line.ends_with('\r') {
+                self.line_ending = LineEnding::CrLf;
+            } else if (self.line_ending == LineEnding::CrLf) != line.ends_with('\r') {
+                self.keep_carriage_returns();
+            }
+            self.push_line(line);
+            start += len + 1;
+        }
+        text.drain(..start);
+        if last {
+            self.missing_final_newline = !text.is_empty();
+            if self.missing_final_newline {
+                let line = mem::take(text);
+                self.push_line(&line);
+            }
+        }
+    }
+
+    // Switches a file found to have mixed line endings to LF, putting back
+    // the `\r` taken off the rows read so far.
+    fn keep_carriage_returns(&mut self) {
+        if self.line_ending == LineEnding::CrLf {
+            for row in self.rows.iter_mut() {
+                row.insert(row.len(), '\r');
+            }
+            self.line_ending = LineEnding::Lf;
+        }
+        self.mixed_line_endings = true;
+    }
+
+    fn push_line(&mut self, line: &str) {
+        let line = match self.line_ending {
+            LineEnding::CrLf => line.strip_suffix('\r').unwrap_or(line),
+            LineEnding::Lf => line,
+        };
+        self.rows.push(Row::from(line));
+    }
+
+    /// How much of the file has been read, in percent, while it is loading.
+    pub fn loading_progress(&self) -> Option<u64> {
+        self.loader
+            .as_ref()
+            .map(|loader| loader.read.saturating_mul(100) / loader.len.max(1))
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loader.is_some()
+    }
+
+    pub fn is_large(&self) -> bool {
+        self.large
+    }
+
+    /// Whether only the start of the file was read.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Whether some of the file's bytes weren't valid in its encoding, so
+    /// are shown as raw bytes and saved unchanged.
+    pub fn has_raw_bytes(&self) -> bool {
+        self.raw_bytes
+    }
+
+    pub fn is_hex(&self) -> bool {
+        self.hex.is_some()
+    }
+
+    /// The bytes being edited while the document is shown in hex.
+    pub fn bytes(&self) -> &[u8] {
+        self.hex.as_deref().unwrap_or_default()
+    }
+
+    /// Overwrites byte `index` while the document is shown in hex.
+    pub fn set_byte(&mut self, index: usize, byte: u8) {
+        if let Some(old) = self.hex.as_mut().and_then(|bytes| bytes.get_mut(index)) {
+            if *old != byte {
+                *old = byte;
+                self.dirty = true;
+            }
+        }
+    }
+
+    /// Shows the document as the bytes it would be saved as, to be edited
+    /// and saved exactly as they are.
+    pub fn show_hex(&mut self) -> Result<(), Error> {
+        if self.hex.is_some() {
+            return Ok(());
+        }
+        self.hex = Some(self.to_bytes()?);
+        self.raw_bytes = false;
+        Ok(())
+    }
+
+    /// Goes back to showing the document as text, decoding its bytes in
+    /// the encoding the user chose or the one they look like.
+    pub fn show_text(&mut self) -> Result<(), Error> {
+        let bytes = match &self.hex {
+            Some(bytes) => bytes,
+            None => return Ok(()),
+        };
+        let encoding = match self.chosen_encoding {
+            Some(encoding) => FileEncoding::with_encoding(encoding, bytes),
+            None => FileEncoding::detect(bytes, true),
+        };
+        let mut text = String::new();
+        self.raw_bytes = decode(&mut encoding.new_decoder(), &[], bytes, &mut text, true);
+        self.hex = None;
+        self.encoding = encoding;
+        self.rows = BlockList::default();
+        self.line_ending = LineEnding::default();
+        self.mixed_line_endings = false;
+        self.push_lines(&mut text, true);
+        self.history = History::default();
+        // Edits made in hex can't be undone, so neither can their save.
+        if self.dirty {
+            self.history.forget_saved();
+        }
+        self.hightlighted = 0;
+        Ok(())
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.large || self.read_only || self.loader.is_some()
+    }
+
+    /// An unnamed, unmodified document holding `text`.
+    pub fn from_text(text: &str) -> Self {
+        Self {
+            rows: rows_from(text),
+            ..Self::default()
+        }
+    }
+
+    /// The rows joined with newlines, whatever the file's line endings.
+    pub fn contents(&self) -> String {
+        let mut contents = String::new();
+        for (index, row) in self.rows.iter().enumerate() {
+            if index > 0 {
+                contents.push('\n');
+            }
+            row.chunks().for_each(|chunk| contents.push_str(chunk));
+        }
+        contents
+    }
+
+    /// Replaces the text with `text`, such as unsaved changes read back
+    /// from a swap file. The document is left modified and can't be undone
+    /// past this point.
+    pub fn recover(&mut self, text: &str) {
+        self.rows = rows_from(text);
+        self.history = History::default();
+        self.history.forget_saved();
+        self.dirty = true;
+        self.hightlighted = 0;
+    }
+
+    pub fn file_type(&self) -> String {
+        self.file_type.name()
+    }
+
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    pub fn has_mixed_line_endings(&self) -> bool {
+        self.mixed_line_endings
+    }
+
+    pub fn encoding(&self) -> FileEncoding {
+        self.encoding
+    }
+
+    /// Changes the line endings the file is saved with. Converting a file
+    /// with mixed line endings takes the `\r` kept in its rows off first.
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        if self.line_ending != line_ending {
+            if self.mixed_line_endings {
+                self.strip_carriage_returns();
+                self.mixed_line_endings = false;
+            }
+            self.line_ending = line_ending;
+            self.history.forget_saved();
+            self.dirty = true;
+        }
+    }
+
+    // Deletes the `\r` at the end of each row as one undo step.
+    fn strip_carriage_returns(&mut self) {
+        let mut operations = Vec::new();
+        for y in 0..self.rows.len() {
+            let len = self.rows[y].len();
+            if let Some(x) = len.checked_sub(1).filter(|x| self.rows[y].grapheme(*x) == Some("\r")) {
+                self.delete_between(&Position { x, y }, len, &mut operations);
+            }
+        }
+        if !operations.is_empty() {
+            let start = Position::default();
+            self.history.record_step(operations, &start, &start);
+        }
     }
 
     pub fn row(&self, index: usize) -> Option<&Row> {
@@ -52,22 +494,33 @@ impl Document {
         if at.y > self.len() {
             return;
         }
+        // Typed text is escaped like text read from a file, unlike text
+        // yanked back, whose raw byte characters are still raw bytes.
+        if let Some(escaped) = encoding::escape_raw_range(self.encoding.encoding(), &c.to_string()) {
+            self.insert_str(at, &escaped);
+            return;
+        }
         self.dirty = true;
         if c == '\n' {
             self.insert_newline(at);
             return;
         }
         if at.y == self.len() {
-            let mut row = Row::default();
-            row.insert(0, c);
-            row.hightlight(None);
-            self.rows.push(row);
-        // } else if at.y < self.len() {
-        } else {
-            let row = self.rows.get_mut(at.y).unwrap();
-            row.insert(at.x, c);
-            row.hightlight(None);
+            self.push_row();
+            self.history.record(Operation::PushRow, at, at);
         }
+        let at = Position {
+            x: cmp::min(at.x, self.rows[at.y].len()),
+            y: at.y,
+        };
+        let text = c.to_string();
+        self.insert_text(&at, &text);
+        let after = Position {
+            x: at.x.saturating_add(1),
+            y: at.y,
+        };
+        let before = at.clone();
+        self.history.record(Operation::Insert { at, text }, &before, &after);
     }
 
     pub fn delete(&mut self, at: &Position) {
@@ -75,43 +528,360 @@ impl Document {
         if at.y >= len {
             return;
         }
-        self.dirty = true;
-        if at.x == self.rows.get_mut(at.y).unwrap().len() && at.y + 1 < len {
-            let next_row = self.rows.remove(at.y + 1);
-            let row = self.rows.get_mut(at.y).unwrap();
-            row.append(&next_row);
-            row.hightlight(None);
-        } else {
-            let row = self.rows.get_mut(at.y).unwrap();
-            row.delete(at.x);
-            row.hightlight(None);
+        let row = &self.rows[at.y];
+        if at.x == row.len() && at.y + 1 < len {
+            self.dirty = true;
+            self.join_row(at);
+            self.history.record(Operation::Join { at: at.clone() }, at, at);
+        } else if let Some(text) = row.grapheme(at.x) {
+            self.dirty = true;
+            let text = text.to_string();
+            self.delete_text(at, &text);
+            self.history.record(
+                Operation::Delete {
+                    at: at.clone(),
+                    text,
+                },
+                at,
+                at,
+            );
         }
     }
 
     fn insert_newline(&mut self, at: &Position) {
-        if at.y > self.len() {
+        let after = Position {
+            x: 0,
+            y: at.y.saturating_add(1),
+        };
+        if at.y == self.len() {
+            self.push_row();
+            self.history.record(Operation::PushRow, at, &after);
             return;
         }
+        let at = Position {
+            x: cmp::min(at.x, self.rows[at.y].len()),
+            y: at.y,
+        };
+        self.split_row(&at);
+        self.history.record(Operation::Split { at: at.clone() }, &at, &after);
+    }
+
+    /// Inserts `text`, which may span several lines, as one undo step and
+    /// returns the position right after it.
+    pub fn insert_str(&mut self, at: &Position, text: &str) -> Position {
+        if at.y > self.len() || text.is_empty() {
+            return at.clone();
+        }
+        let mut operations = Vec::new();
         if at.y == self.len() {
-            self.rows.push(Row::default());
+            self.push_row();
+            operations.push(Operation::PushRow);
+        }
+        let mut position = Position {
+            x: cmp::min(at.x, self.rows[at.y].len()),
+            y: at.y,
+        };
+        let before = position.clone();
+        for (index, line) in text.split('\n').enumerate() {
+            if index > 0 {
+                self.split_row(&position);
+                operations.push(Operation::Split {
+                    at: position.clone(),
+                });
+                position = Position {
+                    x: 0,
+                    y: position.y.saturating_add(1),
+                };
+            }
+            if !line.is_empty() {
+                self.insert_text(&position, line);
+                operations.push(Operation::Insert {
+                    at: position.clone(),
+                    text: line.to_string(),
+                });
+                position.x = position.x.saturating_add(line[..].graphemes(true).count());
+            }
+        }
+        self.dirty = true;
+        self.history.record_step(operations, &before, &position);
+        position
+    }
+
+    /// Returns the text from `start` up to (not including) `end`, with rows
+    /// joined by newlines.
+    pub fn text_range(&self, start: &Position, end: &Position) -> String {
+        let mut lines = Vec::new();
+        for y in start.y..=end.y {
+            if let Some(row) = self.rows.get(y) {
+                let from = if y == start.y { start.x } else { 0 };
+                let to = if y == end.y { end.x } else { row.len() };
+                lines.push(row.substring(from, to));
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// Deletes everything from `start` up to (not including) `end` as one undo step.
+    pub fn delete_range(&mut self, start: &Position, end: &Position) {
+        if start.y >= self.len() || end.y >= self.len() {
             return;
         }
-        // let new_row = self.rows.get_mut(at.y).unwrap().split(at.x);
+        let mut operations = Vec::new();
+        let mut remaining = end.y.saturating_sub(start.y);
+        let to = if remaining == 0 {
+            end.x
+        } else {
+            self.rows[start.y].len()
+        };
+        self.delete_between(start, to, &mut operations);
+        while remaining > 0 {
+            self.join_row(start);
+            operations.push(Operation::Join { at: start.clone() });
+            remaining -= 1;
+            let to = if remaining == 0 {
+                start.x.saturating_add(end.x)
+            } else {
+                self.rows[start.y].len()
+            };
+            self.delete_between(start, to, &mut operations);
+        }
+        if !operations.is_empty() {
+            self.dirty = true;
+            self.history.record_step(operations, start, start);
+        }
+    }
+
+    fn delete_between(&mut self, at: &Position, to: usize, operations: &mut Vec<Operation>) {
+        let text = self.rows[at.y].substring(at.x, to);
+        if !text.is_empty() {
+            self.delete_text(at, &text);
+            operations.push(Operation::Delete {
+                at: at.clone(),
+                text,
+            });
+        }
+    }
+
+    /// Reverts the last edit step and returns where the cursor was before it.
+    pub fn undo(&mut self) -> Option<Position> {
+        let step = self.history.pop_undo()?;
+        for operation in step.operations.iter().rev() {
+            match operation {
+                Operation::Insert { at, text } => self.delete_text(at, text),
+                Operation::Delete { at, text } => self.insert_text(at, text),
+                Operation::Split { at } => self.join_row(at),
+                Operation::Join { at } => self.split_row(at),
+                Operation::PushRow => {
+                    self.rows.pop();
+                    self.hightlighted = cmp::min(self.hightlighted, self.rows.len());
+                }
+            }
+        }
+        let cursor = step.cursor_before.clone();
+        self.history.push_redo(step);
+        self.dirty = !self.history.is_saved();
+        Some(cursor)
+    }
+
+    /// Re-applies the last undone step and returns where the cursor was after it.
+    pub fn redo(&mut self) -> Option<Position> {
+        let step = self.history.pop_redo()?;
+        for operation in &step.operations {
+            match operation {
+                Operation::Insert { at, text } => self.insert_text(at, text),
+                Operation::Delete { at, text } => self.delete_text(at, text),
+                Operation::Split { at } => self.split_row(at),
+                Operation::Join { at } => self.join_row(at),
+                Operation::PushRow => self.push_row(),
+            }
+        }
+        let cursor = step.cursor_after.clone();
+        self.history.push_undo(step);
+        self.dirty = !self.history.is_saved();
+        Some(cursor)
+    }
+
+    /// Makes the edits until `end_edit_group` undo as one step.
+    pub fn begin_edit_group(&mut self) {
+        self.history.begin_group();
+    }
+
+    pub fn end_edit_group(&mut self) {
+        self.history.end_group();
+    }
+
+    pub fn seal_history(&mut self) {
+        self.history.seal();
+    }
+
+    /// The rows split or joined since this was last called, oldest first.
+    pub fn take_row_changes(&mut self) -> Vec<RowChange> {
+        mem::take(&mut self.row_changes)
+    }
+
+    fn insert_text(&mut self, at: &Position, text: &str) {
+        if let Some(row) = self.rows.get_mut(at.y) {
+            row.insert_str(at.x, text);
+            self.update_hightlighting(at.y, at.y);
+        }
+    }
+
+    fn delete_text(&mut self, at: &Position, text: &str) {
+        if let Some(row) = self.rows.get_mut(at.y) {
+            row.delete(at.x, at.x.saturating_add(text[..].graphemes(true).count()));
+            self.update_hightlighting(at.y, at.y);
+        }
+    }
+
+    fn split_row(&mut self, at: &Position) {
         let current_row = &mut self.rows[at.y];
-        let mut new_row = current_row.split(at.x);
-        current_row.hightlight(None);
-        new_row.hightlight(None);
+        let new_row = current_row.split(at.x);
+        self.row_changes.push(RowChange::Split(Position {
+            x: current_row.len(),
+            y: at.y,
+        }));
         self.rows.insert(at.y + 1, new_row);
+        if at.y < self.hightlighted {
+            self.hightlighted += 1;
+        }
+        self.update_hightlighting(at.y, at.y + 1);
+    }
+
+    fn join_row(&mut self, at: &Position) {
+        let next_row = self.rows.remove(at.y + 1);
+        if at.y + 1 < self.hightlighted {
+            self.hightlighted -= 1;
+        }
+        let row = &mut self.rows[at.y];
+        self.row_changes.push(RowChange::Join(Position { x: row.len(), y: at.y }));
+        row.append(next_row);
+        self.update_hightlighting(at.y, at.y);
     }
 
+    fn push_row(&mut self) {
+        self.rows.push(Row::default());
+        let last = self.rows.len() - 1;
+        self.update_hightlighting(last, last);
+    }
+
+    /// Re-highlights rows starting at `from`, always going through `through`
+    /// and then on only while the state a row ends in keeps changing. Rows
+    /// that haven't been highlighted yet are left until they are shown, and
+    /// so are the ones below a row too long to finish on every edit.
+    fn update_hightlighting(&mut self, from: usize, through: usize) {
+        if from >= self.hightlighted {
+            return;
+        }
+        let mut state = self.start_state(from);
+        let options = self.file_type.hightlighting_options();
+        let word = self.word.as_deref();
+        for (y, row) in (from..self.hightlighted).zip(self.rows.iter_mut_from(from)) {
+            let previous = row.end_state().cloned();
+            row.hightlight(options, word, &state);
+            let Some(end_state) = row.end_state() else {
+                self.hightlighted = y.saturating_add(1);
+                break;
+            };
+            if y >= through && previous.as_ref() == Some(end_state) {
+                break;
+            }
+            state = end_state.clone();
+        }
+    }
+
+    // What row `y` starts inside of, finishing the row above if its
+    // highlighting was cut short.
+    fn start_state(&mut self, y: usize) -> hightlighting::State {
+        match y.checked_sub(1).and_then(|y| self.rows.get_mut(y)) {
+            Some(row) => row
+                .finish_hightlight(self.file_type.hightlighting_options(), self.word.as_deref())
+                .clone(),
+            None => hightlighting::State::None,
+        }
+    }
+
+    /// Writes the document to a temporary file next to the target and
+    /// renames it into place once it is safely on disk, so a failed save
+    /// leaves the original untouched. A symlink is followed and its target
+    /// replaced, and the original file's permissions are kept.
     pub fn save(&mut self) -> Result<(), Error> {
+        if self.is_read_only() {
+            return Err(Error::new(ErrorKind::PermissionDenied, "the document is read-only"));
+        }
         if let Some(file_name) = &self.filename {
-            let mut file = fs::File::create(file_name)?;
-            for row in &self.rows {
-                file.write_all(row.as_bytes())?;
-                file.write_all(b"\n")?;
+            let bytes = self.to_bytes()?;
+            let target = resolve_symlinks(Path::new(file_name))?;
+            let (temp_path, file) = create_temp_file(&target)?;
+            if let Err(error) = write_file(file, &bytes, &target, &temp_path) {
+                let _ = fs::remove_file(&temp_path);
+                return Err(error);
+            }
+            self.disk_state = fs::metadata(&target)
+                .ok()
+                .map(|metadata| DiskState::new(&metadata, &bytes));
+            // Make the rename itself durable; not every platform can sync a
+            // directory, so failing here doesn't fail the save.
+            if let Some(dir) = target.parent() {
+                let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+                if let Ok(dir) = fs::File::open(dir) {
+                    let _ = dir.sync_all();
+                }
             }
+            self.file_type = FileType::from(file_name);
             self.dirty = false;
+            self.history.mark_saved();
+            self.hightlight(None);
+        }
+        Ok(())
+    }
+
+    // The file as it will be written, with its line endings and encoding.
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        if let Some(bytes) = &self.hex {
+            return Ok(bytes.clone());
+        }
+        let mut text = String::new();
+        let last = self.rows.len().saturating_sub(1);
+        for (index, row) in self.rows.iter().enumerate() {
+            row.chunks().for_each(|chunk| text.push_str(chunk));
+            if index < last || !self.missing_final_newline {
+                text.push_str(self.line_ending.as_str());
+            }
+        }
+        self.encoding.encode(&text)
+    }
+
+    /// Whether the file has been changed or deleted by something else
+    /// since it was opened or last saved. Only the modification time and
+    /// size are checked unless they differ, so a file that was merely
+    /// touched doesn't count.
+    pub fn changed_on_disk(&self) -> bool {
+        let (file_name, disk_state) = match (&self.filename, &self.disk_state) {
+            (Some(file_name), Some(disk_state)) => (file_name, disk_state),
+            _ => return false,
+        };
+        let metadata = match fs::metadata(file_name) {
+            Ok(metadata) => metadata,
+            Err(_) => return true,
+        };
+        if metadata.modified().ok() == disk_state.modified && metadata.len() == disk_state.len {
+            return false;
+        }
+        fs::read(file_name).map_or(true, |bytes| hash_bytes(&bytes) != disk_state.hash)
+    }
+
+    /// Reads the file again, dropping any unsaved changes and the undo
+    /// history. An encoding the user chose is kept; otherwise it is
+    /// guessed again.
+    pub fn reload(&mut self) -> Result<(), Error> {
+        self.reload_with(self.chosen_encoding)
+    }
+
+    /// Reads the file again as `encoding`, or guessing the encoding if it
+    /// is `None`, dropping any unsaved changes and the undo history.
+    pub fn reload_with(&mut self, encoding: Option<&'static Encoding>) -> Result<(), Error> {
+        if let Some(file_name) = &self.filename {
+            *self = Self::open_with_encoding(file_name, encoding)?;
         }
         Ok(())
     }
@@ -157,6 +927,58 @@ impl Document {
         }
         None
     }
+    /// Returns the start of the next regex match at or after `at` and the x where it ends.
+    pub fn find_regex(&self, regex: &Regex, at: &Position) -> Option<(Position, usize)> {
+        let mut x = at.x;
+        for (y, row) in (at.y..).zip(self.rows.iter_from(at.y)) {
+            if let Some((start, end)) = row.find_regex(regex, x) {
+                return Some((Position { x: start, y }, end));
+            }
+            x = 0;
+        }
+        None
+    }
+
+    /// Replaces the regex match starting at `at`, expanding capture groups in
+    /// `replacement`, and returns the position right after the inserted text.
+    pub fn replace_regex(
+        &mut self,
+        regex: &Regex,
+        at: &Position,
+        replacement: &str,
+    ) -> Option<Position> {
+        let row = self.rows.get(at.y)?;
+        let (start, end) = row.find_regex(regex, at.x)?;
+        let text = row.expand_regex(regex, start, replacement)?;
+        let deleted = row.substring(start, end);
+        let at = Position { x: start, y: at.y };
+        let after = Position {
+            x: start.saturating_add(text[..].graphemes(true).count()),
+            y: at.y,
+        };
+
+        let mut operations = Vec::new();
+        if !deleted.is_empty() {
+            self.delete_text(&at, &deleted);
+            operations.push(Operation::Delete {
+                at: at.clone(),
+                text: deleted,
+            });
+        }
+        if !text.is_empty() {
+            self.insert_text(&at, &text);
+            operations.push(Operation::Insert {
+                at: at.clone(),
+                text,
+            });
+        }
+        if !operations.is_empty() {
+            self.dirty = true;
+            self.history.record_step(operations, &at, &after);
+        }
+        Some(after)
+    }
+
     // pub fn find(&self, query: &str, after: &Position, direction: SearchDirection) -> Option<Position> {
     //     let mut x = after.x;
     //     for (y, row) in self.rows.iter().enumerate().skip(after.y) {
@@ -168,10 +990,450 @@ impl Document {
     //     None
     // }
 
+    /// Highlights matches of `word` from now on, starting over from the top.
     pub fn hightlight(&mut self, word: Option<&str>) {
-        for row in &mut self.rows {
-            row.hightlight(word);
+        self.word = word.map(str::to_string);
+        self.hightlighted = 0;
+    }
+
+    /// Highlights every row down to `y` that hasn't been yet. The state a
+    /// row starts in depends on the rows above it, so those are all done
+    /// first.
+    pub fn hightlight_through(&mut self, y: usize) {
+        let end = cmp::min(y.saturating_add(1), self.rows.len());
+        if self.large || self.hightlighted >= end {
+            return;
+        }
+        let from = self.hightlighted;
+        let mut state = self.start_state(from);
+        let options = self.file_type.hightlighting_options();
+        let word = self.word.as_deref();
+        for row in self.rows.iter_mut_from(from).take(end - from) {
+            row.hightlight(options, word, &state);
+            state = row.finish_hightlight(options, word).clone();
+        }
+        self.hightlighted = end;
+    }
+
+}
+
+// Follows `path` through any symlinks to the file they finally point at,
+// which may not exist yet.
+fn resolve_symlinks(path: &Path) -> Result<PathBuf, Error> {
+    let mut path = path.to_path_buf();
+    for _ in 0..MAX_SYMLINKS {
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                let link = fs::read_link(&path)?;
+                path = match path.parent() {
+                    Some(parent) => parent.join(link),
+                    None => link,
+                };
+            }
+            Ok(_) => return Ok(path),
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(path),
+            Err(error) => return Err(error),
+        }
+    }
+    Err(Error::new(
+        ErrorKind::Other,
+        format!("too many levels of symbolic links: {}", path.display()),
+    ))
+}
+
+// Creates a new hidden file in the same directory as `target`, so it can be
+// renamed over it without crossing file systems.
+fn create_temp_file(target: &Path) -> Result<(PathBuf, fs::File), Error> {
+    let name = target
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    let dir = target.parent().unwrap_or_else(|| Path::new(""));
+    let mut attempt = 0;
+    loop {
+        let temp_path = dir.join(format!(".{}.{}.{}.tmp", name, process::id(), attempt));
+        match fs::OpenOptions::new().write(true).create_new(true).open(&temp_path) {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(error) if error.kind() == ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+fn write_file(file: fs::File, bytes: &[u8], target: &Path, temp_path: &Path) -> Result<(), Error> {
+    let mut file = file;
+    file.write_all(bytes)?;
+    match fs::metadata(target) {
+        Ok(metadata) => file.set_permissions(metadata.permissions())?,
+        Err(error) if error.kind() == ErrorKind::NotFound => (),
+        Err(error) => return Err(error),
+    }
+    file.sync_all()?;
+    fs::rename(temp_path, target)
+}
+
+// The size above which files are opened read-only, in bytes, from
+// HECTO_LARGE_FILE_MB.
+fn large_file_size() -> u64 {
+    env::var("HECTO_LARGE_FILE_MB")
+        .ok()
+        .and_then(|megabytes| megabytes.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_LARGE_FILE_MB)
+        .saturating_mul(1024 * 1024)
+}
+
+// Decodes `bytes` onto the end of `text`, returning whether any of them
+// weren't valid. Those are kept as raw byte characters, so saving writes
+// them back unchanged; `tail` is the end of the bytes decoded before, in
+// case an invalid sequence started there.
+fn decode(decoder: &mut Decoder, tail: &[u8], bytes: &[u8], text: &mut String, last: bool) -> bool {
+    let mut read = 0;
+    let mut had_errors = false;
+    loop {
+        let start = text.len();
+        let (result, len) = decoder.decode_to_string_without_replacement(&bytes[read..], text, last);
+        read += len;
+        if let Some(escaped) = encoding::escape_raw_range(decoder.encoding(), &text[start..]) {
+            text.truncate(start);
+            text.push_str(&escaped);
+        }
+        match result {
+            DecoderResult::InputEmpty => return had_errors,
+            DecoderResult::OutputFull => {
+                let left = bytes.len() - read;
+                text.reserve(decoder.max_utf8_buffer_length(left).unwrap_or(left).max(4));
+            }
+            DecoderResult::Malformed(invalid, after) => {
+                had_errors = true;
+                // Where the invalid bytes end, counting from the start of
+                // `tail`.
+                let end = (tail.len() + read).saturating_sub(usize::from(after));
+                for at in end.saturating_sub(usize::from(invalid))..end {
+                    let byte = if at < tail.len() { tail[at] } else { bytes[at - tail.len()] };
+                    text.push(encoding::raw_byte_char(byte));
+                }
+            }
         }
     }
+}
+
+fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+fn rows_from(text: &str) -> BlockList<Row> {
+    if text.is_empty() {
+        return BlockList::default();
+    }
+    text.split('\n').map(Row::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A path in the temp directory that no other test uses.
+    fn temp_path(name: &str) -> String {
+        env::temp_dir()
+            .join(format!("hecto-{}-{}", process::id(), name))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn at(x: usize, y: usize) -> Position {
+        Position { x, y }
+    }
+
+    #[test]
+    fn undoing_back_to_the_save_is_clean() {
+        let path = temp_path("undo-saved.txt");
+        fs::write(&path, "ab\n").unwrap();
+        let mut document = Document::open(&path).unwrap();
+        document.insert(&at(2, 0), 'c');
+        assert!(document.is_dirty());
+        document.undo();
+        assert!(!document.is_dirty());
+        document.redo();
+        document.save().unwrap();
+        document.insert(&at(3, 0), 'd');
+        document.undo();
+        assert!(!document.is_dirty());
+        document.undo();
+        assert!(document.is_dirty());
+        document.redo();
+        assert!(!document.is_dirty());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn changes_outside_the_history_stay_dirty() {
+        let mut document = Document::from_text("ab");
+        document.set_line_ending(LineEnding::CrLf);
+        document.insert(&at(2, 0), 'c');
+        document.undo();
+        assert!(document.is_dirty());
+
+        let mut document = Document::default();
+        document.recover("recovered");
+        document.insert(&at(0, 0), 'c');
+        document.undo();
+        assert!(document.is_dirty());
+    }
+
+    #[test]
+    fn invalid_bytes_are_saved_unchanged() {
+        let path = temp_path("invalid-bytes.txt");
+        let bytes = b"na\xc3\xafve caf\xc3\xa9 \xe2\x9c\x93 \xff\n\xe2\x82x\n".to_vec();
+        fs::write(&path, &bytes).unwrap();
+        let mut document = Document::open(&path).unwrap();
+        assert_eq!(document.encoding().name(), "UTF-8");
+        assert!(document.has_raw_bytes());
+        assert!(!document.is_read_only());
+        assert_eq!(document.row(0).unwrap().len(), 14);
+        document.insert(&at(0, 2), '!');
+        document.save().unwrap();
+        let mut expected = bytes.clone();
+        expected.extend_from_slice(b"!\n");
+        assert_eq!(fs::read(&path).unwrap(), expected);
+
+        document.show_hex().unwrap();
+        assert_eq!(document.bytes(), expected.as_slice());
+        document.show_text().unwrap();
+        assert!(document.has_raw_bytes());
+        assert_eq!(document.to_bytes().unwrap(), expected);
+        fs::remove_file(&path).unwrap();
+    }
 
+    #[test]
+    fn invalid_sequence_across_pieces_is_kept() {
+        let path = temp_path("invalid-across-pieces.txt");
+        let mut bytes = vec![b'a'; LOAD_PIECE_BYTES - 1];
+        bytes.extend_from_slice(b"\xe2\x82x\xf0\x9f");
+        fs::write(&path, &bytes).unwrap();
+        let mut document = Document::open(&path).unwrap();
+        while document.load_more().unwrap() {}
+        assert!(document.has_raw_bytes());
+        assert_eq!(document.row(0).unwrap().len(), LOAD_PIECE_BYTES + 4);
+        assert_eq!(document.to_bytes().unwrap(), bytes);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn long_rows_finish_hightlighting_when_the_rows_below_are_shown() {
+        let path = temp_path("long-row.rs");
+        fs::write(&path, format!("/*{}\na\n", "x".repeat(20_000))).unwrap();
+        let mut document = Document::open(&path).unwrap();
+        while document.load_more().unwrap() {}
+        fs::remove_file(&path).unwrap();
+        document.hightlight_through(1);
+        assert!(document.row(0).unwrap().end_state() == Some(&hightlighting::State::BlockComment));
+        assert!(document.row(1).unwrap().end_state() == Some(&hightlighting::State::BlockComment));
+
+        document.insert_str(&at(2, 0), "*/");
+        assert!(document.row(0).unwrap().end_state().is_none());
+        document.hightlight_through(1);
+        assert!(document.row(0).unwrap().end_state() == Some(&hightlighting::State::None));
+        assert!(document.row(1).unwrap().end_state() == Some(&hightlighting::State::None));
+    }
+
+    #[test]
+    fn crlf_files_are_saved_with_crlf() {
+        let path = temp_path("crlf.txt");
+        fs::write(&path, "one\r\ntwo\r\n").unwrap();
+        let mut document = Document::open(&path).unwrap();
+        assert!(document.line_ending() == LineEnding::CrLf);
+        assert!(!document.has_mixed_line_endings());
+        assert_eq!(document.contents(), "one\ntwo");
+        document.insert(&at(3, 1), 's');
+        document.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\r\ntwos\r\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn mixed_line_endings_are_kept_until_converted() {
+        let path = temp_path("mixed-endings.txt");
+        fs::write(&path, "one\r\ntwo\nthree\r\n").unwrap();
+        let mut document = Document::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(document.has_mixed_line_endings());
+        assert!(document.line_ending() == LineEnding::Lf);
+        assert_eq!(document.row(0).unwrap().len(), 4);
+        assert_eq!(document.to_bytes().unwrap(), b"one\r\ntwo\nthree\r\n");
+
+        document.set_line_ending(LineEnding::CrLf);
+        assert!(!document.has_mixed_line_endings());
+        assert_eq!(document.to_bytes().unwrap(), b"one\r\ntwo\r\nthree\r\n");
+        document.set_line_ending(LineEnding::Lf);
+        assert_eq!(document.to_bytes().unwrap(), b"one\ntwo\nthree\n");
+    }
+
+    #[test]
+    fn a_missing_final_newline_stays_missing() {
+        for contents in ["one\ntwo", "one\r\ntwo"] {
+            let path = temp_path("no-final-newline.txt");
+            fs::write(&path, contents).unwrap();
+            let mut document = Document::open(&path).unwrap();
+            document.insert(&at(0, 0), 'x');
+            document.save().unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), format!("x{}", contents));
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn characters_like_raw_bytes_are_saved_as_themselves() {
+        let path = temp_path("raw-range.txt");
+        let contents = "a\u{10ff41}b\n";
+        fs::write(&path, contents).unwrap();
+        let mut document = Document::open(&path).unwrap();
+        assert!(!document.has_raw_bytes());
+        document.insert(&at(0, 1), '\u{10ff42}');
+        document.insert_str(&at(0, 1), &encoding::raw_byte_char(b'!').to_string());
+        document.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("{}!\u{10ff42}\n", contents));
+        fs::remove_file(&path).unwrap();
+    }
+
+    // An empty directory in the temp directory that no other test uses.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = PathBuf::from(temp_path(name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    fn dir_entries(dir: &Path) -> Vec<String> {
+        let mut entries: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn saving_through_a_symlink_updates_its_target() {
+        let dir = temp_dir("save-symlink");
+        let target = dir.join("target.txt");
+        let link = dir.join("link.txt");
+        fs::write(&target, "old\n").unwrap();
+        std::os::unix::fs::symlink("target.txt", &link).unwrap();
+        let mut document = Document::open(&link.to_string_lossy()).unwrap();
+        document.insert(&at(3, 0), '!');
+        document.save().unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "old!\n");
+        assert_eq!(dir_entries(&dir), ["link.txt", "target.txt"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn saving_keeps_the_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = temp_path("save-private.txt");
+        fs::write(&path, "secret\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        let mut document = Document::open(&path).unwrap();
+        document.insert(&at(0, 0), '!');
+        document.save().unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_failed_save_leaves_the_file_alone() {
+        let dir = temp_dir("save-failed");
+        let path = dir.join("latin1.txt");
+        fs::write(&path, b"caf\xe9\n").unwrap();
+        let mut document = Document::open(&path.to_string_lossy()).unwrap();
+        assert_eq!(document.encoding().name(), "windows-1252");
+        document.insert(&at(0, 0), '\u{2713}');
+        assert!(document.save().is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"caf\xe9\n");
+        assert_eq!(dir_entries(&dir), ["latin1.txt"]);
+
+        // The file is replaced by a directory the new one can't be renamed
+        // over.
+        document.undo();
+        document.insert(&at(0, 0), 'x');
+        fs::remove_file(&path).unwrap();
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("inside.txt"), "kept").unwrap();
+        assert!(document.save().is_err());
+        assert_eq!(fs::read_to_string(path.join("inside.txt")).unwrap(), "kept");
+        assert_eq!(dir_entries(&dir), ["latin1.txt"]);
+        assert!(document.is_dirty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replacements_expand_capture_groups() {
+        let mut document = Document::from_text("name: alice, name: bob");
+        let regex = Regex::new(r"name: (?P<who>\w+)").unwrap();
+        assert_eq!(document.replace_regex(&regex, &at(0, 0), "${who}!").unwrap().x, 6);
+        assert_eq!(document.contents(), "alice!, name: bob");
+        assert_eq!(document.replace_regex(&regex, &at(6, 0), "[$0/$1]").unwrap().x, 23);
+        assert_eq!(document.contents(), "alice!, [name: bob/bob]");
+        assert!(document.replace_regex(&regex, &at(23, 0), "").is_none());
+
+        let regex = Regex::new("x*").unwrap();
+        assert_eq!(document.replace_regex(&regex, &at(3, 0), "-").unwrap().x, 4);
+        assert_eq!(document.contents(), "ali-ce!, [name: bob/bob]");
+        document.undo();
+        document.undo();
+        assert_eq!(document.contents(), "alice!, name: bob");
+    }
+
+    #[test]
+    fn rehightlighting_stops_once_the_state_settles() {
+        let path = temp_path("settles.rs");
+        fs::write(&path, "x\nx\nx */\nx\n").unwrap();
+        let mut document = Document::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        document.hightlight_through(3);
+        // Rows highlighted again from here on show matches of `x`.
+        document.word = Some("x".to_string());
+        let shows_match = |document: &Document, y: usize| {
+            let cells = document.row(y).unwrap().render(0, 10, None, 4);
+            let x = cells.iter().find(|cell| cell.symbol == "x").unwrap();
+            x.fg == Some(hightlighting::Type::Match.to_color())
+        };
+
+        document.insert_str(&at(0, 0), "/*");
+        let comment = Some(&hightlighting::State::BlockComment);
+        assert!(document.row(1).unwrap().end_state() == comment);
+        assert!(document.row(2).unwrap().end_state() == Some(&hightlighting::State::None));
+        assert_eq!((0..4).map(|y| shows_match(&document, y)).collect::<Vec<_>>(), [true, true, true, false]);
+
+        document.undo();
+        assert!(document.row(1).unwrap().end_state() == Some(&hightlighting::State::None));
+        assert!(!shows_match(&document, 3));
+    }
+
+    #[test]
+    fn changes_on_disk_are_noticed_but_not_our_own() {
+        let path = temp_path("changed-on-disk.txt");
+        fs::write(&path, "one\n").unwrap();
+        let mut document = Document::open(&path).unwrap();
+        while document.load_more().unwrap() {}
+        assert!(!document.changed_on_disk());
+        document.insert(&at(3, 0), '!');
+        document.save().unwrap();
+        assert!(!document.changed_on_disk());
+
+        // Touched, but still the same.
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(5)).unwrap();
+        assert!(!document.changed_on_disk());
+
+        fs::write(&path, "two!\n").unwrap();
+        assert!(document.changed_on_disk());
+        document.reload().unwrap();
+        while document.load_more().unwrap() {}
+        assert!(!document.changed_on_disk());
+        fs::remove_file(&path).unwrap();
+        assert!(document.changed_on_disk());
+    }
 }
@@ -1,8 +1,24 @@
+use std::cmp;
+use std::collections::hash_map::DefaultHasher;
 use std::env;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::ops::Range;
+use std::path::Path;
 
+use crate::clipboard::{KillRing, SystemClipboard};
 use crate::Document;
+use crate::document::LineEnding;
+use crate::encoding::FileEncoding;
+use crate::frame::{Cell, Frame};
+use crate::hex;
+use crate::layout::{Layout, Orientation, Rect};
+use crate::swap::{self, SwapFile};
 use crate::Row;
+use crate::terminal::Backend;
 use crate::Terminal;
+use crate::terminal::{Input, Size};
+use regex::Regex;
 use std::time::Duration;
 use std::time::Instant;
 use termion::color;
@@ -10,8 +26,17 @@ use termion::event::Key;
 
 const STATUS_FG_COLOR: color::Rgb = color::Rgb(63, 63, 63);
 const STATUS_BG_COLOR: color::Rgb = color::Rgb(239, 239, 239);
+const INACTIVE_STATUS_BG_COLOR: color::Rgb = color::Rgb(170, 170, 170);
+const GUTTER_FG_COLOR: color::Rgb = color::Rgb(120, 120, 120);
+const HEX_CURSOR_BG_COLOR: color::Rgb = color::Rgb(68, 68, 120);
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const QUIT_TIMES: u8 = 3;
+const DEFAULT_TAB_WIDTH: usize = 4;
+const SWAP_INTERVAL: Duration = Duration::from_secs(2);
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+// How long to spend reading a large file before checking for keys.
+const LOAD_SLICE: Duration = Duration::from_millis(50);
+const PANE_RESIZE_STEP: i16 = 5;
 
 #[derive(PartialEq, Copy, Clone)]
 pub enum SearchDirection {
@@ -25,6 +50,143 @@ pub struct Position {
     pub y: usize,
 }
 
+/// How the line number gutter is drawn. Relative numbers count lines away
+/// from the cursor; hybrid does the same but shows the cursor's own line
+/// number instead of 0.
+#[derive(PartialEq, Copy, Clone)]
+enum LineNumbers {
+    Off,
+    Absolute,
+    Relative,
+    Hybrid,
+}
+
+impl LineNumbers {
+    fn next(self) -> Self {
+        match self {
+            LineNumbers::Off => LineNumbers::Absolute,
+            LineNumbers::Absolute => LineNumbers::Relative,
+            LineNumbers::Relative => LineNumbers::Hybrid,
+            LineNumbers::Hybrid => LineNumbers::Off,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            LineNumbers::Off => "off",
+            LineNumbers::Absolute => "absolute",
+            LineNumbers::Relative => "relative",
+            LineNumbers::Hybrid => "hybrid",
+        }
+    }
+}
+
+/// Whether long rows are wrapped onto several screen lines instead of being
+/// scrolled horizontally, and if so whether lines break between words.
+#[derive(PartialEq, Copy, Clone)]
+enum SoftWrap {
+    Off,
+    Characters,
+    Words,
+}
+
+impl SoftWrap {
+    fn next(self) -> Self {
+        match self {
+            SoftWrap::Off => SoftWrap::Characters,
+            SoftWrap::Characters => SoftWrap::Words,
+            SoftWrap::Words => SoftWrap::Off,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SoftWrap::Off => "off",
+            SoftWrap::Characters => "characters",
+            SoftWrap::Words => "words",
+        }
+    }
+}
+
+/// How tabs are shown and typed. Read from `HECTO_TAB_WIDTH` (columns per
+/// tab stop, 4 unless set) and `HECTO_INSERT_SPACES` (`1` or `true` to type
+/// spaces up to the next tab stop instead of a tab).
+struct Tabs {
+    width: usize,
+    insert_spaces: bool,
+}
+
+impl Tabs {
+    fn from_env() -> Self {
+        let width = env::var("HECTO_TAB_WIDTH")
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+            .filter(|width| *width > 0)
+            .unwrap_or(DEFAULT_TAB_WIDTH);
+        let insert_spaces = env::var("HECTO_INSERT_SPACES")
+            .map_or(false, |value| matches!(value.trim(), "1" | "true"));
+        Self {
+            width,
+            insert_spaces,
+        }
+    }
+}
+
+/// The fixed end of a selection; the cursor is the other end. A sticky
+/// selection is started with the set-mark key and survives plain cursor
+/// movement, while one started with shift ends on the next unshifted move.
+struct Selection {
+    anchor: Position,
+    sticky: bool,
+}
+
+/// A document along with where it was being viewed and edited. The editor's
+/// own fields hold the current buffer; the rest are kept here while another
+/// one is shown.
+#[derive(Default)]
+struct Buffer {
+    document: Document,
+    cursor_pos: Position,
+    offset: Position,
+    wrap_offset: usize,
+    hex_cursor: HexCursor,
+    swap_written: Option<(Instant, u64)>,
+    swap_disabled: bool,
+    disk_change_reported: bool,
+}
+
+impl Buffer {
+    fn from(document: Document) -> Self {
+        Self {
+            document,
+            ..Self::default()
+        }
+    }
+}
+
+/// One of the areas the screen is split into, showing a buffer with its
+/// own cursor and scroll position. The focused pane's view is kept in the
+/// editor's own fields.
+#[derive(Default)]
+struct Pane {
+    buffer: usize,
+    cursor_pos: Position,
+    offset: Position,
+    wrap_offset: usize,
+    hex_cursor: HexCursor,
+}
+
+/// Where the cursor is in the hex view: the byte it is on, and whether it
+/// is on one of that byte's two hex digits or on it in the text column.
+/// It is kept as an index rather than a row and column, as how many bytes
+/// a row holds depends on the width of the pane.
+#[derive(Default, Clone, Copy)]
+struct HexCursor {
+    index: usize,
+    text: bool,
+    low_nibble: bool,
+}
+
 struct StatusMessage {
     text: String,
     time: Instant,
@@ -41,89 +203,247 @@ impl StatusMessage {
 
 pub struct Editor {
     should_quit: bool,
-    terminal: Terminal,
+    terminal: Box<dyn Backend>,
     cursor_pos: Position,
     offset: Position,
+    // With soft wrap on, how many screen lines of the row at `offset.y` are
+    // scrolled off the top.
+    wrap_offset: usize,
     document: Document,
     status_msg: StatusMessage,
     quit_times: u8,
+    selection: Option<Selection>,
+    kill_ring: KillRing,
+    system_clipboard: SystemClipboard,
+    last_yank: Option<(Position, Position)>,
+    appending_kill: bool,
+    line_numbers: LineNumbers,
+    previous_frame: Option<Frame>,
+    tabs: Tabs,
+    soft_wrap: SoftWrap,
+    hex_cursor: HexCursor,
+    // When the swap file was last written and a hash of what was in it.
+    swap_written: Option<(Instant, u64)>,
+    // Set when the swap file belongs to another editor or can't be read,
+    // so it is left alone.
+    swap_disabled: bool,
+    disk_checked: Instant,
+    disk_change_reported: bool,
+    // Every open buffer; the one at `current` is a placeholder while its
+    // contents are in the fields above.
+    buffers: Vec<Buffer>,
+    current: usize,
+    // Every pane; the focused one is a placeholder like the current buffer.
+    panes: Vec<Pane>,
+    focused: usize,
+    layout: Layout,
+    // Where `layout` puts each pane, by index, and the dividers between
+    // them, worked out again only when the layout or window size changes.
+    pane_rects: Vec<Rect>,
+    dividers: Vec<Rect>,
 }
 
 impl Editor {
     pub fn run(&mut self) {
+        for index in 0..self.buffers.len() {
+            self.switch_buffer(index);
+            self.check_swap();
+        }
+        self.switch_buffer(0);
         loop {
             if let Err(error) = self.refresh_screen() {
-                die(&error)
+                die(self.terminal.as_mut(), &error)
             }
             if self.should_quit {
                 break;
             }
+            // Keep loading until a key comes in, redrawing to show progress.
+            match self.terminal.input_pending() {
+                Ok(false) if self.continue_loading() => continue,
+                Err(error) => die(self.terminal.as_mut(), &error),
+                _ => (),
+            }
             if let Err(error) = self.process_keypress() {
-                die(&error);
+                die(self.terminal.as_mut(), &error);
             }
+            self.update_swap(false);
+            self.check_disk();
         }
     }
 
     pub fn default() -> Self {
         let args: Vec<String> = env::args().collect();
+        let terminal = Terminal::default().expect("initional the termional failed");
+        Self::new(Box::new(terminal), &args)
+    }
+
+    /// Creates an editor drawing to `terminal` and opening the file named
+    /// in `args`, laid out like the process's command line arguments.
+    pub fn new(terminal: Box<dyn Backend>, args: &[String]) -> Self {
         let mut initial_status =
-            String::from("HELP: Ctrl-F = find | Ctrl-S = save | Ctrl-A = quit");
-        let document = if args.len() > 1 {
-            let file_name = &args[1];
+            String::from("HELP: Ctrl-F = find | Ctrl-R = replace | Ctrl-S = save | Ctrl-Z = undo | Ctrl-Y = redo | Ctrl-A = quit");
+        let mut documents = Vec::new();
+        for arg in args.iter().skip(1) {
+            let (file_name, location) = split_location(arg);
+            let file_name = &file_name;
             // Document::open(&file_name).unwrap_or_default()
             let doc = Document::open(file_name);
             if doc.is_ok() {
-                doc.unwrap()
+                let doc = doc.unwrap();
+                if let Some(message) = file_message(&doc) {
+                    initial_status = message;
+                }
+                documents.push((doc, location));
             } else {
                 initial_status = format!("ERR: Could not open file: {}", file_name);
-                Document::default()
+                documents.push((Document::default(), None));
             }
-        } else {
-            Document::default()
-        };
+        }
+        if documents.is_empty() {
+            documents.push((Document::default(), None));
+        }
 
-        Self {
+        let mut editor = Self {
             should_quit: false,
-            terminal: Terminal::default().expect("initional the termional failed"),
+            terminal,
             // cursor_pos: Position { x: 0, y: 0 },
             cursor_pos: Position::default(),
             offset: Position::default(),
+            wrap_offset: 0,
             // document: Document::open(),
-            document,
+            document: Document::default(),
             status_msg: StatusMessage::from(initial_status),
             quit_times: 0,
+            selection: None,
+            kill_ring: KillRing::default(),
+            system_clipboard: SystemClipboard::from_env(),
+            last_yank: None,
+            appending_kill: false,
+            line_numbers: LineNumbers::Off,
+            previous_frame: None,
+            tabs: Tabs::from_env(),
+            soft_wrap: SoftWrap::Off,
+            hex_cursor: HexCursor::default(),
+            swap_written: None,
+            swap_disabled: false,
+            disk_checked: Instant::now(),
+            disk_change_reported: false,
+            buffers: Vec::new(),
+            current: 0,
+            panes: vec![Pane::default()],
+            focused: 0,
+            layout: Layout::Pane(0),
+            pane_rects: Vec::new(),
+            dividers: Vec::new(),
+        };
+        editor.arrange_panes();
+        for (index, (document, location)) in documents.into_iter().enumerate() {
+            editor.buffers.push(Buffer::default());
+            editor.switch_buffer(index);
+            editor.document = document;
+            if let Some(location) = location {
+                editor.go_to(&location);
+            }
         }
+        editor.switch_buffer(0);
+        editor
     }
 
     fn process_keypress(&mut self) -> Result<(), std::io::Error> {
-        let pressed_key = Terminal::read_key()?;
+        let Input {
+            key: pressed_key,
+            shift,
+        } = match self.terminal.read_input()? {
+            Some(input) => input,
+            None => {
+                self.handle_resize();
+                return Ok(());
+            }
+        };
+        if self.document.is_read_only() && edits(pressed_key) {
+            self.status_msg = StatusMessage::from(if self.document.is_loading() {
+                "Can't change the file while it is loading.".to_string()
+            } else {
+                "The file is read-only.".to_string()
+            });
+            return Ok(());
+        }
+        let last_yank = self.last_yank.take();
+        let appending_kill = self.appending_kill;
+        self.appending_kill = false;
         match pressed_key {
+            Key::Alt('h') => self.toggle_hex(),
+            Key::Ctrl('f') if self.document.is_hex() => self.search_bytes(),
+            Key::Char(c) if self.document.is_hex() => self.type_hex(c),
+            Key::Up
+            | Key::Down
+            | Key::Left
+            | Key::Right
+            | Key::PageUp
+            | Key::PageDown
+            | Key::Home
+            | Key::End
+                if self.document.is_hex() =>
+            {
+                self.move_hex_cursor(pressed_key)
+            }
+            key if self.document.is_hex() && text_only(key) => {
+                self.status_msg = StatusMessage::from("Not available in hex mode.".to_string());
+            }
             // Key::Ctrl('c') => panic!("Program end"),
             // Key::Ctrl('a') => self.should_quit = true,
             Key::Ctrl('a') => {
-                if self.quit_times > 0 && self.document.is_dirty() {
+                let dirty = self.dirty_buffer_names();
+                if self.quit_times > 0 && !dirty.is_empty() {
                     self.status_msg = StatusMessage::from(format!(
-                        "WARNING! File has unsaved changes Press CTRL-A {} more times to quit.",
+                        "WARNING! Unsaved changes in {} Press CTRL-A {} more times to quit.",
+                        dirty.join(", "),
                         self.quit_times
                     ));
                     self.quit_times -= 1;
                     return Ok(());
                 }
+                self.remove_all_swaps();
                 self.should_quit = true
             }
+            Key::Ctrl('n') => {
+                self.switch_buffer((self.current + 1) % self.buffers.len());
+                self.show_buffer_name();
+            }
+            Key::Ctrl('p') => {
+                let len = self.buffers.len();
+                self.switch_buffer((self.current + len - 1) % len);
+                self.show_buffer_name();
+            }
+            Key::Ctrl('b') => self.pick_buffer(),
+            Key::Ctrl('o') => self.open_file(),
+            Key::Char('\t') if self.tabs.insert_spaces => {
+                self.document.begin_edit_group();
+                self.delete_selection();
+                let column = self.cursor_column();
+                let spaces = " ".repeat(self.tabs.width - column % self.tabs.width);
+                self.cursor_pos = self.document.insert_str(&self.cursor_pos, &spaces);
+                self.document.end_edit_group();
+            }
             Key::Char(c) => {
+                // Typing over a selection replaces it in one undo step.
+                self.document.begin_edit_group();
+                self.delete_selection();
                 self.document.insert(&self.cursor_pos, c);
+                self.document.end_edit_group();
                 self.move_cursor(Key::Right);
             }
             Key::Backspace => {
-                if self.cursor_pos.x > 0 || self.cursor_pos.y > 0 {
+                if !self.delete_selection() && (self.cursor_pos.x > 0 || self.cursor_pos.y > 0) {
                     self.move_cursor(Key::Left);
                     self.document.delete(&self.cursor_pos);
                 }
                 // self.document.insert(&self.cursor_pos, c);
             }
             Key::Delete => {
-                self.document.delete(&self.cursor_pos);
+                if !self.delete_selection() {
+                    self.document.delete(&self.cursor_pos);
+                }
                 // if self.cursor_pos.x >= 0 && self.cursor_pos.y > 0 {
                 // self.move_cursor(Key::Left);
                 // }
@@ -134,6 +454,81 @@ impl Editor {
             Key::Ctrl('f') => {
                 self.search();
             }
+            Key::Ctrl('r') => {
+                self.replace();
+            }
+            Key::Ctrl('x') => self.cut(appending_kill),
+            Key::Ctrl('c') => self.copy(),
+            Key::Ctrl('v') => self.paste(),
+            Key::Alt('y') => self.yank_pop(last_yank),
+            Key::Ctrl('g') => self.go_to_line(),
+            Key::Ctrl('w') => {
+                self.soft_wrap = self.soft_wrap.next();
+                self.offset.x = 0;
+                self.wrap_offset = 0;
+                self.status_msg =
+                    StatusMessage::from(format!("Soft wrap: {}", self.soft_wrap.name()));
+            }
+            Key::Alt('e') => {
+                let line_ending = match self.document.line_ending() {
+                    LineEnding::Lf => LineEnding::CrLf,
+                    LineEnding::CrLf => LineEnding::Lf,
+                };
+                self.document.set_line_ending(line_ending);
+                self.status_msg =
+                    StatusMessage::from(format!("Line endings: {}", line_ending.name()));
+            }
+            Key::Alt('r') => self.reload(),
+            Key::Alt('c') => self.reopen_with_encoding(),
+            Key::Alt('s') => self.split_pane(Orientation::Horizontal),
+            Key::Alt('v') => self.split_pane(Orientation::Vertical),
+            Key::Alt('o') => self.focus_next_pane(),
+            Key::Alt('q') => self.close_pane(),
+            Key::Alt('+') | Key::Alt('=') => self.resize_pane(PANE_RESIZE_STEP),
+            Key::Alt('-') => self.resize_pane(-PANE_RESIZE_STEP),
+            Key::Alt('t') => {
+                self.tabs.insert_spaces = !self.tabs.insert_spaces;
+                self.status_msg = StatusMessage::from(if self.tabs.insert_spaces {
+                    format!("Tab inserts spaces (width {})", self.tabs.width)
+                } else {
+                    String::from("Tab inserts a tab")
+                });
+            }
+            Key::Ctrl('l') => {
+                self.line_numbers = self.line_numbers.next();
+                self.status_msg = StatusMessage::from(format!(
+                    "Line numbers: {}",
+                    self.line_numbers.name()
+                ));
+            }
+            Key::Null => {
+                if self.selection.take().is_some() {
+                    self.status_msg = StatusMessage::from("Mark cleared.".to_string());
+                } else {
+                    self.selection = Some(Selection {
+                        anchor: self.cursor_pos.clone(),
+                        sticky: true,
+                    });
+                    self.status_msg = StatusMessage::from("Mark set.".to_string());
+                }
+            }
+            Key::Esc => self.selection = None,
+            Key::Ctrl('z') => {
+                self.selection = None;
+                if let Some(position) = self.document.undo() {
+                    self.cursor_pos = position;
+                } else {
+                    self.status_msg = StatusMessage::from("Nothing to undo.".to_string());
+                }
+            }
+            Key::Ctrl('y') => {
+                self.selection = None;
+                if let Some(position) = self.document.redo() {
+                    self.cursor_pos = position;
+                } else {
+                    self.status_msg = StatusMessage::from("Nothing to redo.".to_string());
+                }
+            }
             Key::Insert => {}
             Key::Up
             | Key::Down
@@ -142,9 +537,23 @@ impl Editor {
             | Key::PageUp
             | Key::PageDown
             | Key::End
-            | Key::Home => self.move_cursor(pressed_key),
+            | Key::Home => {
+                self.document.seal_history();
+                if shift {
+                    if self.selection.is_none() {
+                        self.selection = Some(Selection {
+                            anchor: self.cursor_pos.clone(),
+                            sticky: false,
+                        });
+                    }
+                } else if self.selection.as_ref().map_or(false, |selection| !selection.sticky) {
+                    self.selection = None;
+                }
+                self.move_cursor(pressed_key);
+            }
             _ => (),
         }
+        self.follow_row_changes();
         self.scroll();
         if self.quit_times < QUIT_TIMES {
             self.quit_times = QUIT_TIMES;
@@ -153,52 +562,149 @@ impl Editor {
         Ok(())
     }
 
-    fn refresh_screen(&self) -> Result<(), std::io::Error> {
-        Terminal::cursor_hide();
-        Terminal::cursor_pos(&Position::default());
+    // Draws the whole screen into a fresh frame and writes out only what
+    // changed since the last one.
+    fn refresh_screen(&mut self) -> Result<(), std::io::Error> {
         if self.should_quit {
-            Terminal::clear_screen();
-            println!("Goodbye!!!");
-        } else {
-            self.draw_rows();
-            // Terminal::cursor_pos(&self.cursor_pos);
-            self.draw_status_bar();
-            self.draw_status_msg();
-
-            Terminal::cursor_pos(&Position {
-                x: self.cursor_pos.x.saturating_sub(self.offset.x),
-                y: self.cursor_pos.y.saturating_sub(self.offset.y),
-            });
+            self.terminal.cursor_hide();
+            self.terminal.cursor_pos(&Position::default());
+            self.terminal.clear_screen();
+            self.terminal.write("Goodbye!!!\r\n");
+            self.terminal.cursor_show();
+            return self.terminal.flash();
+        }
+        let size = self.terminal.size();
+        let height = size.height as usize;
+        let mut frame = Frame::new(size.width as usize, height.saturating_add(2));
+        for (pane, rect) in self.pane_rects.clone().into_iter().enumerate() {
+            let mut pane_frame = Frame::new(rect.width, rect.height);
+            if pane == self.focused {
+                self.draw_pane(&mut pane_frame, true);
+                let cursor = self.screen_cursor();
+                frame.set_cursor(Position {
+                    x: rect.x.saturating_add(cursor.x),
+                    y: rect.y.saturating_add(cursor.y),
+                });
+            } else {
+                self.with_pane(pane, |editor| editor.draw_pane(&mut pane_frame, false));
+            }
+            frame.blit(rect.x, rect.y, &pane_frame);
+        }
+        for divider in &self.dividers {
+            for y in divider.y..divider.y.saturating_add(divider.height) {
+                frame.set(divider.x, y, Cell::new("│", Some(GUTTER_FG_COLOR), None));
+            }
+        }
+        self.draw_status_msg(&mut frame, height.saturating_add(1));
+
+        let output = frame.diff(self.previous_frame.as_ref());
+        self.previous_frame = Some(frame);
+        self.terminal.write(&output);
+        self.terminal.flash()
+    }
+
+    // Draws the pane being shown into `frame`, which is the pane's size.
+    fn draw_pane(&mut self, frame: &mut Frame, focused: bool) {
+        let bottom = self.offset.y.saturating_add(self.view_size().height as usize);
+        self.document.hightlight_through(bottom);
+        self.draw_rows(frame);
+        self.draw_status_bar(frame, frame.height().saturating_sub(1), focused);
+    }
+
+    // Where the cursor is within its pane.
+    fn screen_cursor(&self) -> Position {
+        if self.document.is_hex() {
+            let per_line = self.hex_bytes_per_line();
+            let column = self.hex_cursor.index % per_line;
+            let x = if self.hex_cursor.text {
+                hex::text_column(column, per_line)
+            } else {
+                hex::hex_column(column, per_line) + self.hex_cursor.low_nibble as usize
+            };
+            // Even one byte a line may not fit in a narrow pane.
+            let width = self.view_size().width as usize;
+            return Position {
+                x: cmp::min(x, width.saturating_sub(1)),
+                y: (self.hex_cursor.index / per_line).saturating_sub(self.offset.y),
+            };
+        }
+        if self.soft_wrap != SoftWrap::Off {
+            return self.wrapped_cursor_pos();
+        }
+        Position {
+            x: self
+                .cursor_column()
+                .saturating_sub(self.offset.x)
+                .saturating_add(self.gutter_width()),
+            y: self.cursor_pos.y.saturating_sub(self.offset.y),
+        }
+    }
+
+    // The screen above the message line, which the panes share.
+    fn pane_area(&self) -> Rect {
+        let size = self.terminal.size();
+        Rect {
+            x: 0,
+            y: 0,
+            width: size.width as usize,
+            height: (size.height as usize).saturating_add(1),
+        }
+    }
+
+    // Works out where each pane goes, after the layout or the window size
+    // has changed.
+    fn arrange_panes(&mut self) {
+        let (panes, dividers) = self.layout.arrange(self.pane_area());
+        self.pane_rects = vec![self.pane_area(); self.panes.len()];
+        for (pane, rect) in panes {
+            if let Some(slot) = self.pane_rects.get_mut(pane) {
+                *slot = rect;
+            }
+        }
+        self.dividers = dividers;
+    }
+
+    // The text area of the pane being shown, without its status bar.
+    fn view_size(&self) -> Size {
+        let rect = self.pane_rects.get(self.focused).copied().unwrap_or_else(|| self.pane_area());
+        Size {
+            width: rect.width as u16,
+            height: rect.height.saturating_sub(1) as u16,
         }
-        Terminal::cursor_show();
-        Terminal::flash()
     }
 
-    fn draw_rows(&self) {
-        let height = self.terminal.size().height;
-        // for row in 0..self.terminal.size().height - 1 {
+    fn draw_rows(&self, frame: &mut Frame) {
+        if self.document.is_hex() {
+            self.draw_hex_rows(frame);
+            return;
+        }
+        if self.soft_wrap != SoftWrap::Off {
+            self.draw_wrapped_rows(frame);
+            return;
+        }
+        let height = self.view_size().height as usize;
+        // for row in 0..self.view_size().height - 1 {
         for terminal_row in 0..height {
-            Terminal::clear_current_line();
             // if terminal_row == height / 3 {
             // if let Some(row) = self
             //     .document
             //     .row(terminal_row as usize + self.offset.y as usize)
-            if let Some(row) = self
-                .document
-                .row(self.offset.y.saturating_add(terminal_row as usize))
-            {
-                self.draw_row(row);
+            let y = self.offset.y.saturating_add(terminal_row);
+            if y < self.document.len() {
+                let start = self.offset.x;
+                let end = self.offset.x.saturating_add(self.text_width());
+                self.draw_row(frame, y, terminal_row, start..end, true);
             } else if self.document.is_empty() && terminal_row == height / 3 {
-                self.draw_welcome_msg();
+                self.draw_welcome_msg(frame, terminal_row);
             } else {
-                println!("~\r");
+                frame.put_str(0, terminal_row, "~", None, None);
             }
         }
     }
 
     fn move_cursor(&mut self, key: Key) {
         let Position { mut x, mut y } = self.cursor_pos;
-        // let size = self.terminal.size();
+        // let size = self.view_size();
         // let width = size.width.saturating_sub(1) as usize;
         let mut width = if let Some(row) = self.document.row(y) {
             row.len()
@@ -208,10 +714,15 @@ impl Editor {
         // let height = size.height.saturating_sub(1) as usize;
         let height = self.document.len() as usize;
 
-        // let terminal_width = self.terminal.size().width as usize;
-        let terminal_height = self.terminal.size().height as usize;
+        // let terminal_width = self.view_size().width as usize;
+        let terminal_height = self.view_size().height as usize;
 
         match key {
+            Key::Up | Key::Down if self.soft_wrap != SoftWrap::Off => {
+                let position = self.move_wrapped(key == Key::Down);
+                x = position.x;
+                y = position.y;
+            }
             Key::Up => y = y.saturating_sub(1),
             Key::Down => {
                 if y < height {
@@ -260,6 +771,17 @@ impl Editor {
             _ => (),
         }
 
+        // Moving between rows keeps the cursor in the same screen column
+        // rather than at the same grapheme.
+        let vertical = match key {
+            Key::Up | Key::Down => self.soft_wrap == SoftWrap::Off,
+            Key::PageUp | Key::PageDown => true,
+            _ => false,
+        };
+        if y != self.cursor_pos.y && vertical {
+            let column = self.cursor_column();
+            x = self.document.row(y).map_or(0, |row| row.index_at(column, self.tabs.width));
+        }
         width = if let Some(row) = self.document.row(y) {
             row.len()
         } else {
@@ -272,31 +794,384 @@ impl Editor {
         self.cursor_pos = Position { x, y }
     }
 
-    fn draw_welcome_msg(&self) {
-        let mut welcome_msg = format!("Hecto editor -- version {}\r", VERSION);
-        let width = self.terminal.size().width as usize;
+    /// Returns the ordered start and end of the selection, if there is one.
+    fn selection_range(&self) -> Option<(Position, Position)> {
+        let anchor = &self.selection.as_ref()?.anchor;
+        let cursor = &self.cursor_pos;
+        if (anchor.y, anchor.x) == (cursor.y, cursor.x) {
+            return None;
+        }
+        if (anchor.y, anchor.x) < (cursor.y, cursor.x) {
+            Some((anchor.clone(), cursor.clone()))
+        } else {
+            Some((cursor.clone(), anchor.clone()))
+        }
+    }
+
+    fn selected_in_row(&self, row: &Row, y: usize) -> Option<Range<usize>> {
+        let (start, end) = self.selection_range()?;
+        if y < start.y || y > end.y {
+            return None;
+        }
+        let from = if y == start.y { start.x } else { 0 };
+        let to = if y == end.y { end.x } else { row.len() };
+        Some(from..to)
+    }
+
+    /// Deletes the selected text, if any, and returns whether it did.
+    fn delete_selection(&mut self) -> bool {
+        let range = self.selection_range();
+        self.selection = None;
+        if let Some((start, end)) = range {
+            self.document.delete_range(&start, &end);
+            self.cursor_pos = start;
+            return true;
+        }
+        false
+    }
+
+    // The current line including its newline, for cutting or copying when
+    // nothing is selected.
+    fn line_range(&self) -> Option<(Position, Position)> {
+        let y = self.cursor_pos.y;
+        let row = self.document.row(y)?;
+        let end = if y.saturating_add(1) < self.document.len() {
+            Position { x: 0, y: y + 1 }
+        } else {
+            Position { x: row.len(), y }
+        };
+        Some((Position { x: 0, y }, end))
+    }
+
+    fn cut(&mut self, appending: bool) {
+        if let Some((start, end)) = self.selection_range() {
+            self.kill_ring.kill(self.document.text_range(&start, &end));
+            self.delete_selection();
+            self.status_msg = StatusMessage::from("Cut selection.".to_string());
+            self.export_kill();
+        } else if let Some((start, end)) = self.line_range() {
+            let text = self.document.text_range(&start, &end);
+            if appending {
+                self.kill_ring.append(&text);
+            } else {
+                self.kill_ring.kill(text);
+            }
+            self.document.delete_range(&start, &end);
+            self.cursor_pos = start;
+            self.appending_kill = true;
+            self.export_kill();
+        }
+    }
+
+    fn copy(&mut self) {
+        let range = self.selection_range().or_else(|| self.line_range());
+        if let Some((start, end)) = range {
+            self.kill_ring.kill(self.document.text_range(&start, &end));
+            self.selection = None;
+            self.status_msg = StatusMessage::from("Copied.".to_string());
+            self.export_kill();
+        }
+    }
+
+    // Sends the newest kill to the system clipboard as well.
+    fn export_kill(&mut self) {
+        if let Some(text) = self.kill_ring.newest() {
+            if let Err(error) = self.system_clipboard.copy(text, self.terminal.as_mut()) {
+                self.status_msg = StatusMessage::from(format!("Clipboard error: {}", error));
+            }
+        }
+    }
+
+    fn paste(&mut self) {
+        let text = match self.kill_ring.yank() {
+            Some(text) => text.to_string(),
+            None => {
+                self.status_msg = StatusMessage::from("Kill ring is empty.".to_string());
+                return;
+            }
+        };
+        self.document.begin_edit_group();
+        self.delete_selection();
+        let start = self.cursor_pos.clone();
+        let end = self.document.insert_str(&start, &text);
+        self.document.end_edit_group();
+        self.cursor_pos = end.clone();
+        self.last_yank = Some((start, end));
+    }
+
+    // Replaces the text just pasted with the next older kill.
+    fn yank_pop(&mut self, last_yank: Option<(Position, Position)>) {
+        let (start, end) = match last_yank {
+            Some(range) => range,
+            None => {
+                self.status_msg =
+                    StatusMessage::from("Previous command was not a paste.".to_string());
+                return;
+            }
+        };
+        let text = match self.kill_ring.rotate() {
+            Some(text) => text.to_string(),
+            None => return,
+        };
+        self.document.begin_edit_group();
+        self.document.delete_range(&start, &end);
+        let end = self.document.insert_str(&start, &text);
+        self.document.end_edit_group();
+        self.cursor_pos = end.clone();
+        self.last_yank = Some((start, end));
+    }
+
+    // Draws the lines of bytes in view. The byte under the cursor is marked
+    // in the column the cursor isn't in, to show where it is in both.
+    fn draw_hex_rows(&self, frame: &mut Frame) {
+        let bytes = self.document.bytes();
+        let per_line = self.hex_bytes_per_line();
+        let (left_bar, right_bar) = hex::bar_columns(per_line);
+        for terminal_row in 0..self.view_size().height as usize {
+            let y = self.offset.y.saturating_add(terminal_row);
+            if y >= hex::lines(bytes.len(), per_line) {
+                frame.put_str(0, terminal_row, "~", None, None);
+                continue;
+            }
+            let offset_text = hex::offset_text(y, per_line);
+            frame.put_str(0, terminal_row, &offset_text, Some(GUTTER_FG_COLOR), None);
+            frame.put_str(left_bar, terminal_row, "|", Some(GUTTER_FG_COLOR), None);
+            frame.put_str(right_bar, terminal_row, "|", Some(GUTTER_FG_COLOR), None);
+            let start = y * per_line;
+            let end = cmp::min(start + per_line, bytes.len());
+            for (column, byte) in bytes[start..end].iter().enumerate() {
+                let at_cursor = start + column == self.hex_cursor.index;
+                let mark = |marked: bool| if marked { Some(HEX_CURSOR_BG_COLOR) } else { None };
+                let hex_text = format!("{:02x}", byte);
+                let text = hex::printable(*byte).to_string();
+                let hex_bg = mark(at_cursor && self.hex_cursor.text);
+                let text_bg = mark(at_cursor && !self.hex_cursor.text);
+                frame.put_str(hex::hex_column(column, per_line), terminal_row, &hex_text, None, hex_bg);
+                frame.put_str(hex::text_column(column, per_line), terminal_row, &text, None, text_bg);
+            }
+        }
+    }
+
+    fn draw_welcome_msg(&self, frame: &mut Frame, terminal_row: usize) {
+        let mut welcome_msg = format!("Hecto editor -- version {}", VERSION);
+        let width = frame.width();
         let len = welcome_msg.len();
         let padding = width.saturating_sub(len) / 2;
         let spaces = " ".repeat(padding);
         welcome_msg = format!("~{}{}", spaces, welcome_msg);
-        welcome_msg.truncate(width);
-        println!("{}\r", welcome_msg);
+        frame.put_str(0, terminal_row, &welcome_msg, None, None);
+    }
+
+    // Draws screen columns `columns` of row `y`, with its line number if
+    // this is the row's first screen line.
+    fn draw_row(
+        &self,
+        frame: &mut Frame,
+        y: usize,
+        terminal_row: usize,
+        columns: Range<usize>,
+        first_line: bool,
+    ) {
+        let row = match self.document.row(y) {
+            Some(row) => row.render(
+                columns.start,
+                columns.end,
+                self.selected_in_row(row, y),
+                self.tabs.width,
+            ),
+            None => return,
+        };
+        let x = if first_line {
+            self.draw_gutter(frame, y, terminal_row)
+        } else {
+            self.gutter_width()
+        };
+        frame.put_cells(x, terminal_row, row);
+    }
+
+    fn draw_wrapped_rows(&self, frame: &mut Frame) {
+        let height = self.view_size().height as usize;
+        let mut y = self.offset.y;
+        let mut line = self.wrap_offset;
+        let mut lines = self.wrap_lines(y);
+        for terminal_row in 0..height {
+            if y >= self.document.len() {
+                if self.document.is_empty() && terminal_row == height / 3 {
+                    self.draw_welcome_msg(frame, terminal_row);
+                } else {
+                    frame.put_str(0, terminal_row, "~", None, None);
+                }
+                continue;
+            }
+            if let Some(row) = self.document.row(y) {
+                let start = row.width_to(lines[line], self.tabs.width);
+                let end = match lines.get(line.saturating_add(1)) {
+                    Some(next) => row.width_to(*next, self.tabs.width),
+                    None => start.saturating_add(self.text_width()),
+                };
+                self.draw_row(frame, y, terminal_row, start..end, line == 0);
+            }
+            line += 1;
+            if line >= lines.len() {
+                y += 1;
+                line = 0;
+                lines = self.wrap_lines(y);
+            }
+        }
     }
 
-    pub fn draw_row(&self, row: &Row) {
-        let width = self.terminal.size().width as usize;
-        let start = self.offset.x as usize;
-        // let end = self.offset.x + self.terminal.size().width as usize;
-        let end = self.offset.x.saturating_add(width);
+    // Where each screen line of row `y` starts when soft wrapped.
+    fn wrap_lines(&self, y: usize) -> Vec<usize> {
+        self.document.row(y).map_or_else(
+            || vec![0],
+            |row| {
+                row.wrap(
+                    self.text_width(),
+                    self.tabs.width,
+                    self.soft_wrap == SoftWrap::Words,
+                )
+            },
+        )
+    }
 
-        let row = row.render(start, end);
-        println!("{}\r", row);
+    // Which of its row's screen lines `pos` is on, out of `lines`.
+    fn wrap_line_of(pos: &Position, lines: &[usize]) -> usize {
+        lines.iter().rposition(|start| *start <= pos.x).unwrap_or(0)
     }
 
-    fn scroll(&mut self) {
+    // The screen line before (row `y`, line `line`), if there is one.
+    fn previous_wrap_line(&self, y: usize, line: usize) -> Option<(usize, usize)> {
+        if line > 0 {
+            Some((y, line - 1))
+        } else if y > 0 {
+            Some((y - 1, self.wrap_lines(y - 1).len() - 1))
+        } else {
+            None
+        }
+    }
+
+    fn wrapped_cursor_pos(&self) -> Position {
+        let lines = self.wrap_lines(self.cursor_pos.y);
+        let line = Self::wrap_line_of(&self.cursor_pos, &lines);
+        let line_column = self
+            .document
+            .row(self.cursor_pos.y)
+            .map_or(0, |row| row.width_to(lines[line], self.tabs.width));
+        let mut screen_y = line;
+        for y in self.offset.y..self.cursor_pos.y {
+            screen_y += self.wrap_lines(y).len();
+        }
+        Position {
+            x: self
+                .cursor_column()
+                .saturating_sub(line_column)
+                .saturating_add(self.gutter_width()),
+            y: screen_y.saturating_sub(self.wrap_offset),
+        }
+    }
+
+    // Moves up or down one screen line of wrapped text, keeping the cursor
+    // in the same column of the line where it can.
+    fn move_wrapped(&self, down: bool) -> Position {
+        let Position { x, y } = self.cursor_pos;
+        let lines = self.wrap_lines(y);
+        let line = Self::wrap_line_of(&self.cursor_pos, &lines);
+        let column = match self.document.row(y) {
+            Some(row) => row
+                .width_to(x, self.tabs.width)
+                .saturating_sub(row.width_to(lines[line], self.tabs.width)),
+            None => 0,
+        };
+        let target = if down {
+            if line.saturating_add(1) < lines.len() {
+                Some((y, line + 1))
+            } else if y < self.document.len() {
+                Some((y + 1, 0))
+            } else {
+                None
+            }
+        } else {
+            self.previous_wrap_line(y, line)
+        };
+        let (y, line) = match target {
+            Some(target) => target,
+            None => return self.cursor_pos.clone(),
+        };
+        let row = match self.document.row(y) {
+            Some(row) => row,
+            None => return Position { x: 0, y },
+        };
+        let lines = self.wrap_lines(y);
+        let start = row.width_to(lines[line], self.tabs.width);
+        let mut x = row.index_at(start.saturating_add(column), self.tabs.width);
+        // Stay on this screen line rather than the start of the next one.
+        if let Some(next) = lines.get(line + 1) {
+            x = cmp::min(x, next.saturating_sub(1));
+        }
+        Position { x, y }
+    }
+
+    // The terminal has already taken on its new size, so nothing drawn for
+    // the old one can be reused; redraw in full and bring the cursor back
+    // into view.
+    fn handle_resize(&mut self) {
+        self.previous_frame = None;
+        self.arrange_panes();
+        self.scroll();
+    }
+
+    fn gutter_width(&self) -> usize {
+        if self.line_numbers == LineNumbers::Off {
+            return 0;
+        }
+        let digits = cmp::max(self.document.len(), 1).to_string().len();
+        digits.saturating_add(1)
+    }
+
+    // The columns left for row content once the gutter is drawn.
+    fn text_width(&self) -> usize {
+        (self.view_size().width as usize).saturating_sub(self.gutter_width())
+    }
+
+    // Returns the column the row's text starts at.
+    fn draw_gutter(&self, frame: &mut Frame, y: usize, terminal_row: usize) -> usize {
+        let cursor_y = self.cursor_pos.y;
+        let number = match self.line_numbers {
+            LineNumbers::Off => return 0,
+            LineNumbers::Absolute => y.saturating_add(1),
+            LineNumbers::Relative => cmp::max(y, cursor_y) - cmp::min(y, cursor_y),
+            LineNumbers::Hybrid if y == cursor_y => y.saturating_add(1),
+            LineNumbers::Hybrid => cmp::max(y, cursor_y) - cmp::min(y, cursor_y),
+        };
+        let width = self.gutter_width().saturating_sub(1);
+        let gutter = format!("{:>width$} ", number, width = width);
+        frame.put_str(0, terminal_row, &gutter, Some(GUTTER_FG_COLOR), None)
+    }
+
+    // The screen column the cursor is at within its row.
+    fn cursor_column(&self) -> usize {
         let Position { x, y } = self.cursor_pos;
-        let width = self.terminal.size().width as usize;
-        let height = self.terminal.size().height as usize;
+        self.document.row(y).map_or(0, |row| row.width_to(x, self.tabs.width))
+    }
+
+    fn scroll(&mut self) {
+        if self.document.is_hex() {
+            self.scroll_hex();
+            return;
+        }
+        if self.soft_wrap != SoftWrap::Off {
+            self.scroll_wrapped();
+            return;
+        }
+        let Position { x: index, y } = self.cursor_pos;
+        let x = self.cursor_column();
+        // Keep all of a wide grapheme under the cursor in view.
+        let cursor_width = self
+            .document
+            .row(y)
+            .map_or(1, |row| cmp::max(row.width_at(index, self.tabs.width), 1));
+        let width = self.text_width();
+        let height = self.view_size().height as usize;
 
         let offset = &mut self.offset;
 
@@ -308,20 +1183,67 @@ impl Editor {
 
         if x < offset.x {
             offset.x = x;
-        } else if x >= offset.x.saturating_add(width) {
-            offset.x = x.saturating_sub(width).saturating_add(1);
+        } else if x.saturating_add(cursor_width) > offset.x.saturating_add(width) {
+            offset.x = x.saturating_add(cursor_width).saturating_sub(width);
         }
     }
 
-    fn draw_status_bar(&self) {
-        // let spaces = " ".repeat(self.terminal.size().width as usize);
+    fn scroll_hex(&mut self) {
+        let height = self.view_size().height as usize;
+        let y = self.hex_index() / self.hex_bytes_per_line();
+        self.offset.x = 0;
+        if y < self.offset.y {
+            self.offset.y = y;
+        } else if y >= self.offset.y.saturating_add(height) {
+            self.offset.y = y.saturating_sub(height).saturating_add(1);
+        }
+    }
+
+    // Scrolls by screen lines so the cursor's line is in view.
+    fn scroll_wrapped(&mut self) {
+        self.offset.x = 0;
+        let height = self.view_size().height as usize;
+        let top_lines = self.wrap_lines(self.offset.y).len();
+        self.wrap_offset = cmp::min(self.wrap_offset, top_lines.saturating_sub(1));
+        let top = (self.offset.y, self.wrap_offset);
+
+        let y = self.cursor_pos.y;
+        let line = Self::wrap_line_of(&self.cursor_pos, &self.wrap_lines(y));
+        if (y, line) < top {
+            self.offset.y = y;
+            self.wrap_offset = line;
+            return;
+        }
+        // The lowest top that still shows the cursor's line.
+        let mut lowest = (y, line);
+        for _ in 1..height {
+            if lowest == top {
+                return;
+            }
+            match self.previous_wrap_line(lowest.0, lowest.1) {
+                Some(previous) => lowest = previous,
+                None => return,
+            }
+        }
+        if lowest > top {
+            self.offset.y = lowest.0;
+            self.wrap_offset = lowest.1;
+        }
+    }
+
+    fn draw_status_bar(&self, frame: &mut Frame, terminal_row: usize, focused: bool) {
+        // let spaces = " ".repeat(self.view_size().width as usize);
         let mut status;
-        let width = self.terminal.size().width as usize;
+        let width = self.view_size().width as usize;
 
-        let modified_indicator = if self.document.is_dirty() {
-            " (modeified)"
+        let modified_indicator = if let Some(percent) = self.document.loading_progress() {
+            format!(" (loading {}%)", percent)
+        } else if self.document.is_dirty() {
+            " (modeified)".to_string()
+        } else if self.document.is_read_only() {
+            " (read-only)".to_string()
         } else {
-            ""
+            String::new()
         };
 
         let mut file_name = "[No Name]".to_string();
@@ -329,21 +1251,43 @@ impl Editor {
             file_name = name.clone();
             file_name.truncate(20);
         }
-        // status = format!("{} - {} lines", file_name, self.document.len());
-        status = format!(
-            "{} - {} lines{}",
-            file_name,
-            self.document.len(),
-            modified_indicator
-        );
-        let line_indicator = format!(
-            // "{}/{}
-            "{}/{}",
-            // self.cursor_pos.x.saturating_add(1),
-            // self.document.row(self.cursor_pos.y).unwrap().len(),
-            self.cursor_pos.y.saturating_add(1),
-            self.document.len()
-        );
+        if self.buffers.len() > 1 {
+            file_name = format!("[{}/{}] {}", self.current + 1, self.buffers.len(), file_name);
+        }
+        let line_indicator;
+        if self.document.is_hex() {
+            let len = self.document.bytes().len();
+            status = format!("{} - {} bytes{}", file_name, len, modified_indicator);
+            line_indicator = format!(
+                "{} | hex | {:08x}/{:08x}",
+                self.document.file_type(),
+                self.hex_index(),
+                len
+            );
+        } else {
+            // status = format!("{} - {} lines", file_name, self.document.len());
+            status = format!(
+                "{} - {} lines{}",
+                file_name,
+                self.document.len(),
+                modified_indicator
+            );
+            line_indicator = format!(
+                // "{}/{}
+                "{} | {} | {} | {}/{}",
+                self.document.file_type(),
+                self.document.encoding().name(),
+                if self.document.has_mixed_line_endings() {
+                    "Mixed"
+                } else {
+                    self.document.line_ending().name()
+                },
+                // self.cursor_pos.x.saturating_add(1),
+                // self.document.row(self.cursor_pos.y).unwrap().len(),
+                self.cursor_pos.y.saturating_add(1),
+                self.document.len()
+            );
+        }
         let len = status.len() + line_indicator.len();
         // if width > len {
         //     status.push_str(&" ".repeat(width - len));
@@ -353,37 +1297,54 @@ impl Editor {
         status = format!("{}{}", status, line_indicator);
         status.truncate(width);
 
-        Terminal::set_bg_color(STATUS_BG_COLOR);
-        Terminal::set_fg_color(STATUS_FG_COLOR);
-        println!("{}\r", status);
-        Terminal::unset_bg_color();
-        Terminal::unset_fg_color();
+        let bg_color = if focused {
+            STATUS_BG_COLOR
+        } else {
+            INACTIVE_STATUS_BG_COLOR
+        };
+        frame.put_str(0, terminal_row, &status, Some(STATUS_FG_COLOR), Some(bg_color));
     }
 
-    fn draw_status_msg(&self) {
-        Terminal::clear_current_line();
+    fn draw_status_msg(&self, frame: &mut Frame, terminal_row: usize) {
         let message = &self.status_msg;
         if Instant::now() - message.time < Duration::new(5, 0) {
-            let mut test = message.text.clone();
-            test.truncate(self.terminal.size().width as usize);
-            print!("{}", test);
+            frame.put_str(0, terminal_row, &message.text, None, None);
         }
     }
 
-    fn prompt<C>(&mut self, prompt: &str, mut callback: C) -> Result<Option<String>, std::io::Error>
+    fn prompt<C>(&mut self, prompt: &str, callback: C) -> Result<Option<String>, std::io::Error>
+    where
+        C: FnMut(&mut Self, Key, &String),
+    {
+        let result = self.prompt_input(prompt, callback)?;
+        Ok(result.filter(|result| !result.is_empty()))
+    }
+
+    /// Like `prompt`, but an empty answer is returned as `Some("")` and only
+    /// ESC gives `None`.
+    fn prompt_input<C>(
+        &mut self,
+        prompt: &str,
+        mut callback: C,
+    ) -> Result<Option<String>, std::io::Error>
     where
         C: FnMut(&mut Self, Key, &String),
     {
         let mut result = String::new();
+        let mut cancelled = false;
         loop {
             self.status_msg = StatusMessage::from(format!("{}{}", prompt, result));
             self.refresh_screen()?;
-            let key = Terminal::read_key()?;
+            let key = match self.terminal.read_key()? {
+                Some(key) => key,
+                None => {
+                    self.handle_resize();
+                    continue;
+                }
+            };
             match key {
                 Key::Backspace => {
-                    if !result.is_empty() {
-                        result.truncate(result.len() - 1);
-                    }
+                    result.pop();
                 }
                 Key::Char('\n') => break,
                 Key::Char(c) => {
@@ -393,6 +1354,7 @@ impl Editor {
                 }
                 Key::Esc => {
                     result.truncate(0);
+                    cancelled = true;
                     break;
                 }
                 _ => (),
@@ -400,7 +1362,7 @@ impl Editor {
             callback(self, key, &result);
         }
         self.status_msg = StatusMessage::from(String::new());
-        if result.is_empty() {
+        if cancelled {
             return Ok(None);
         }
         Ok(Some(result))
@@ -416,12 +1378,733 @@ impl Editor {
             }
             self.document.filename = new_name;
         }
-        if self.document.save().is_ok() {
-            self.status_msg = StatusMessage::from("File saved successfully.".to_string());
-        } else {
-            self.status_msg = StatusMessage::from("Error writiing file!".to_string());
-        }
-    }
+        if self.document.changed_on_disk() {
+            match self.ask("WARNING! File changed on disk. Save over it? (y)es (n)o (r)eload") {
+                Some('y') => (),
+                Some('r') => {
+                    self.reload();
+                    return;
+                }
+                _ => {
+                    self.status_msg = StatusMessage::from("Save aborted.".to_string());
+                    return;
+                }
+            }
+        }
+        match self.document.save() {
+            Ok(()) => {
+                self.status_msg = StatusMessage::from("File saved successfully.".to_string());
+                self.remove_swap();
+                self.disk_change_reported = false;
+            }
+            Err(error) => {
+                self.status_msg = StatusMessage::from(format!("Error writing file: {}", error));
+            }
+        }
+    }
+
+    // Shows `question` and waits for a character key in answer; `None` if
+    // it is dismissed with Esc instead.
+    fn ask(&mut self, question: &str) -> Option<char> {
+        loop {
+            self.status_msg = StatusMessage::from(question.to_string());
+            if self.refresh_screen().is_err() {
+                return None;
+            }
+            match self.terminal.read_key() {
+                Ok(Some(Key::Char(c))) => return Some(c),
+                Ok(Some(Key::Esc)) | Err(_) => return None,
+                Ok(None) => self.handle_resize(),
+                _ => (),
+            }
+        }
+    }
+
+    // Reads the file from disk again, keeping the cursor where it was as
+    // far as the new text allows.
+    fn reload(&mut self) {
+        if self.document.filename.is_none() {
+            self.status_msg = StatusMessage::from("No file to reload.".to_string());
+            return;
+        }
+        if self.document.is_dirty()
+            && self.ask("Discard unsaved changes and reload? (y)es (n)o") != Some('y')
+        {
+            self.status_msg = StatusMessage::from("Reload aborted.".to_string());
+            return;
+        }
+        if let Err(error) = self.document.reload() {
+            self.status_msg = StatusMessage::from(format!("Could not reload file: {}", error));
+            return;
+        }
+        self.reloaded();
+        self.status_msg = StatusMessage::from(
+            file_message(&self.document).unwrap_or_else(|| "Reloaded from disk.".to_string()),
+        );
+    }
+
+    // Reads the file again as an encoding the user names, for when the
+    // guess was wrong. Saving then writes it back in that encoding.
+    fn reopen_with_encoding(&mut self) {
+        if self.document.filename.is_none() {
+            self.status_msg = StatusMessage::from("No file to reopen.".to_string());
+            return;
+        }
+        let label = match self.prompt("Reopen with encoding: ", |_, _, _| {}).unwrap_or(None) {
+            Some(label) => label,
+            None => return,
+        };
+        let encoding = match FileEncoding::for_label(&label) {
+            Some(encoding) => encoding,
+            None => {
+                self.status_msg = StatusMessage::from(format!("Unknown encoding: {}", label));
+                return;
+            }
+        };
+        if self.document.is_dirty()
+            && self.ask("Discard unsaved changes and reopen? (y)es (n)o") != Some('y')
+        {
+            self.status_msg = StatusMessage::from("Reopen aborted.".to_string());
+            return;
+        }
+        if let Err(error) = self.document.reload_with(Some(encoding)) {
+            self.status_msg = StatusMessage::from(format!("Could not reopen file: {}", error));
+            return;
+        }
+        self.reloaded();
+        self.status_msg = StatusMessage::from(file_message(&self.document).unwrap_or_else(|| {
+            format!("Reopened as {}.", self.document.encoding().name())
+        }));
+    }
+
+    // Puts the cursor back within the text after the file has been read
+    // again, keeping it where it was as far as the new text allows. That
+    // needs all of the text, not just its first piece.
+    fn reloaded(&mut self) {
+        self.remove_swap();
+        self.disk_change_reported = false;
+        self.selection = None;
+        self.finish_loading();
+        self.clamp_cursor();
+        self.scroll();
+    }
+
+    // Looks for changes made to the file by something else, at most every
+    // `DISK_CHECK_INTERVAL`, and says so once per change.
+    fn check_disk(&mut self) {
+        if self.disk_change_reported || self.disk_checked.elapsed() < DISK_CHECK_INTERVAL {
+            return;
+        }
+        self.disk_checked = Instant::now();
+        if self.document.changed_on_disk() {
+            self.disk_change_reported = true;
+            self.status_msg =
+                StatusMessage::from("File changed on disk. Press Alt-R to reload.".to_string());
+        }
+    }
+
+    // Reads more of the current document if it is still loading, for a
+    // moment at most, and returns whether there is still more to read.
+    fn continue_loading(&mut self) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < LOAD_SLICE {
+            match self.document.load_more() {
+                Ok(true) => (),
+                Ok(false) => {
+                    if self.document.has_raw_bytes() || self.document.is_truncated() {
+                        self.status_msg = StatusMessage::from(
+                            file_message(&self.document).unwrap_or_default(),
+                        );
+                    }
+                    return false;
+                }
+                Err(error) => {
+                    self.status_msg = StatusMessage::from(format!(
+                        "Could not read all of the file, so it is read-only: {}",
+                        error
+                    ));
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    // Reads the rest of the current document straight away, for when all
+    // of it is needed.
+    fn finish_loading(&mut self) {
+        while self.continue_loading() {
+            if let Err(error) = self.refresh_screen() {
+                die(self.terminal.as_mut(), &error);
+            }
+        }
+    }
+
+    // Offers to recover unsaved changes left in a swap file by an editor
+    // that didn't exit cleanly.
+    fn check_swap(&mut self) {
+        let file_name = match &self.document.filename {
+            Some(file_name) => file_name.clone(),
+            None => return,
+        };
+        if self.document.is_hex() {
+            return;
+        }
+        let swap = SwapFile::for_file(&file_name);
+        let recovered = match swap.read() {
+            Ok(Some(recovered)) => recovered,
+            Ok(None) => return,
+            Err(error) => {
+                self.swap_disabled = true;
+                self.status_msg =
+                    StatusMessage::from(format!("Could not read swap file: {}", error));
+                return;
+            }
+        };
+        if recovered.owner_is_running() {
+            self.swap_disabled = true;
+            self.status_msg = StatusMessage::from(format!(
+                "WARNING! {} is being edited by another hecto (pid {})",
+                file_name, recovered.pid
+            ));
+            return;
+        }
+        self.finish_loading();
+        let contents = self.document.contents();
+        if recovered.text == contents {
+            let _ = swap.remove();
+            return;
+        }
+
+        // Pressing d swaps the diff in for the document and back again.
+        let mut other = Buffer::from(Document::from_text(
+            &swap::diff(&contents, &recovered.text).join("\n"),
+        ));
+        let mut showing_diff = false;
+        loop {
+            self.status_msg = StatusMessage::from(format!(
+                "Found unsaved changes in {}: (r)ecover (d)iff (x) discard",
+                swap.path().display()
+            ));
+            if self.refresh_screen().is_err() {
+                break;
+            }
+            match self.terminal.read_key() {
+                Ok(Some(Key::Char('d'))) => {
+                    self.exchange_buffer(&mut other);
+                    showing_diff = !showing_diff;
+                }
+                Ok(Some(Key::Char('r'))) => {
+                    if showing_diff {
+                        self.exchange_buffer(&mut other);
+                        showing_diff = false;
+                    }
+                    self.document.recover(&recovered.text);
+                    self.status_msg = StatusMessage::from(
+                        "Recovered unsaved changes; save to keep them.".to_string(),
+                    );
+                    break;
+                }
+                Ok(Some(Key::Char('x'))) => {
+                    if showing_diff {
+                        self.exchange_buffer(&mut other);
+                        showing_diff = false;
+                    }
+                    self.status_msg = match swap.remove() {
+                        Ok(()) => StatusMessage::from("Discarded unsaved changes.".to_string()),
+                        Err(error) => StatusMessage::from(format!(
+                            "Could not remove swap file: {}",
+                            error
+                        )),
+                    };
+                    break;
+                }
+                Ok(Some(key @ (Key::Up | Key::Down | Key::PageUp | Key::PageDown))) => {
+                    self.move_cursor(key);
+                    self.scroll();
+                }
+                Ok(None) => self.handle_resize(),
+                Err(_) => break,
+                _ => (),
+            }
+        }
+        if showing_diff {
+            self.exchange_buffer(&mut other);
+        }
+        self.scroll();
+    }
+
+    // Swaps the current buffer's document and view with `buffer`.
+    fn exchange_buffer(&mut self, buffer: &mut Buffer) {
+        self.exchange_document(buffer);
+        mem::swap(&mut self.cursor_pos, &mut buffer.cursor_pos);
+        mem::swap(&mut self.offset, &mut buffer.offset);
+        mem::swap(&mut self.wrap_offset, &mut buffer.wrap_offset);
+        mem::swap(&mut self.hex_cursor, &mut buffer.hex_cursor);
+        self.selection = None;
+        self.last_yank = None;
+    }
+
+    // Swaps only the document and what is tracked about its file, leaving
+    // the view alone.
+    fn exchange_document(&mut self, buffer: &mut Buffer) {
+        mem::swap(&mut self.document, &mut buffer.document);
+        mem::swap(&mut self.swap_written, &mut buffer.swap_written);
+        mem::swap(&mut self.swap_disabled, &mut buffer.swap_disabled);
+        mem::swap(&mut self.disk_change_reported, &mut buffer.disk_change_reported);
+    }
+
+    fn exchange_view(&mut self, pane: &mut Pane) {
+        mem::swap(&mut self.cursor_pos, &mut pane.cursor_pos);
+        mem::swap(&mut self.offset, &mut pane.offset);
+        mem::swap(&mut self.wrap_offset, &mut pane.wrap_offset);
+        mem::swap(&mut self.hex_cursor, &mut pane.hex_cursor);
+    }
+
+    // Makes buffer `index` the current one without touching the view.
+    fn load_document(&mut self, index: usize) {
+        if index == self.current {
+            return;
+        }
+        self.follow_row_changes();
+        self.update_swap(true);
+        let mut buffer = mem::take(&mut self.buffers[self.current]);
+        self.exchange_document(&mut buffer);
+        self.buffers[self.current] = buffer;
+        self.current = index;
+        let mut buffer = mem::take(&mut self.buffers[index]);
+        self.exchange_document(&mut buffer);
+        self.buffers[index] = buffer;
+    }
+
+    // Runs `f` with pane `index` in place of the focused one, such as to
+    // draw it.
+    fn with_pane<F>(&mut self, index: usize, f: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        let mut pane = mem::take(&mut self.panes[index]);
+        let focused = mem::replace(&mut self.focused, index);
+        let selection = self.selection.take();
+        let mut buffer = if pane.buffer == self.current {
+            None
+        } else {
+            Some(mem::take(&mut self.buffers[pane.buffer]))
+        };
+        if let Some(buffer) = &mut buffer {
+            self.exchange_document(buffer);
+        }
+        let current = mem::replace(&mut self.current, pane.buffer);
+        self.exchange_view(&mut pane);
+        f(self);
+        self.exchange_view(&mut pane);
+        self.current = current;
+        if let Some(mut buffer) = buffer {
+            self.exchange_document(&mut buffer);
+            self.buffers[pane.buffer] = buffer;
+        }
+        self.selection = selection;
+        self.focused = focused;
+        self.panes[index] = pane;
+    }
+
+    fn focus_pane(&mut self, index: usize) {
+        if index == self.focused || index >= self.panes.len() {
+            return;
+        }
+        let mut pane = mem::take(&mut self.panes[self.focused]);
+        self.exchange_view(&mut pane);
+        self.panes[self.focused] = pane;
+        self.focused = index;
+        let mut pane = mem::take(&mut self.panes[index]);
+        self.load_document(pane.buffer);
+        self.exchange_view(&mut pane);
+        self.panes[index] = pane;
+        self.selection = None;
+        self.last_yank = None;
+        // The document may have been edited in another pane since.
+        self.clamp_cursor();
+        self.scroll();
+    }
+
+    // Moves the cursors of the other panes showing the current document
+    // along with the text they were on, where rows were split or joined.
+    fn follow_row_changes(&mut self) {
+        let changes = self.document.take_row_changes();
+        if changes.is_empty() {
+            return;
+        }
+        for (index, pane) in self.panes.iter_mut().enumerate() {
+            if index == self.focused || pane.buffer != self.current {
+                continue;
+            }
+            for change in &changes {
+                pane.cursor_pos = change.follow(&pane.cursor_pos);
+                pane.offset.y = change.follow_row(pane.offset.y);
+            }
+        }
+    }
+
+    fn focus_next_pane(&mut self) {
+        let order = self.layout.panes();
+        let position = order.iter().position(|pane| *pane == self.focused).unwrap_or(0);
+        self.focus_pane(order[(position + 1) % order.len()]);
+    }
+
+    // Splits the focused pane in two, both showing the current buffer.
+    fn split_pane(&mut self, orientation: Orientation) {
+        let pane = self.panes.len();
+        self.panes.push(Pane {
+            buffer: self.current,
+            cursor_pos: self.cursor_pos.clone(),
+            offset: self.offset.clone(),
+            wrap_offset: self.wrap_offset,
+            hex_cursor: self.hex_cursor,
+        });
+        self.layout.split(self.focused, pane, orientation);
+        self.arrange_panes();
+        self.scroll();
+    }
+
+    fn close_pane(&mut self) {
+        if self.panes.len() == 1 {
+            self.status_msg = StatusMessage::from("Can't close the only pane.".to_string());
+            return;
+        }
+        let closing = self.focused;
+        self.focus_next_pane();
+        self.layout.remove(closing);
+        self.panes.remove(closing);
+        if self.focused > closing {
+            self.focused -= 1;
+        }
+        self.arrange_panes();
+        self.scroll();
+    }
+
+    fn resize_pane(&mut self, delta: i16) {
+        if !self.layout.resize(self.focused, delta) {
+            self.status_msg = StatusMessage::from("There is no split to resize.".to_string());
+        }
+        self.arrange_panes();
+        self.scroll();
+    }
+
+    // Keeps the cursor within the document after its text has changed.
+    fn clamp_cursor(&mut self) {
+        if self.document.is_hex() {
+            let last = self.document.bytes().len().saturating_sub(1);
+            self.set_hex_index(cmp::min(self.hex_index(), last));
+            return;
+        }
+        let y = cmp::min(self.cursor_pos.y, self.document.len());
+        let width = self.document.row(y).map_or(0, Row::len);
+        self.cursor_pos = Position {
+            x: cmp::min(self.cursor_pos.x, width),
+            y,
+        };
+    }
+
+    fn switch_buffer(&mut self, index: usize) {
+        if index == self.current || index >= self.buffers.len() {
+            return;
+        }
+        // The buffer won't be checked again until it is switched back to.
+        self.update_swap(true);
+        let mut buffer = mem::take(&mut self.buffers[self.current]);
+        self.exchange_buffer(&mut buffer);
+        self.buffers[self.current] = buffer;
+        self.current = index;
+        let mut buffer = mem::take(&mut self.buffers[index]);
+        self.exchange_buffer(&mut buffer);
+        self.buffers[index] = buffer;
+        self.panes[self.focused].buffer = index;
+        self.clamp_cursor();
+        self.scroll();
+    }
+
+    fn show_buffer_name(&mut self) {
+        self.status_msg = StatusMessage::from(format!(
+            "Buffer {}/{}: {}",
+            self.current + 1,
+            self.buffers.len(),
+            buffer_name(&self.document)
+        ));
+    }
+
+    // The document in buffer `index`, wherever it is currently kept.
+    fn buffer_document(&self, index: usize) -> &Document {
+        if index == self.current {
+            &self.document
+        } else {
+            &self.buffers[index].document
+        }
+    }
+
+    fn dirty_buffer_names(&self) -> Vec<String> {
+        (0..self.buffers.len())
+            .map(|index| self.buffer_document(index))
+            .filter(|document| document.is_dirty())
+            .map(buffer_name)
+            .collect()
+    }
+
+    // Lists the open buffers in place of the document and switches to the
+    // one picked with Enter.
+    fn pick_buffer(&mut self) {
+        let list: Vec<String> = (0..self.buffers.len())
+            .map(|index| {
+                let document = self.buffer_document(index);
+                let modified = if document.is_dirty() { " (modified)" } else { "" };
+                format!("{} {}{}", index + 1, buffer_name(document), modified)
+            })
+            .collect();
+        let mut list = Buffer::from(Document::from_text(&list.join("\n")));
+        list.cursor_pos.y = self.current;
+        self.exchange_buffer(&mut list);
+        self.scroll();
+        let mut picked = None;
+        loop {
+            self.status_msg =
+                StatusMessage::from("Pick a buffer: Enter to switch, Esc to cancel".to_string());
+            if self.refresh_screen().is_err() {
+                break;
+            }
+            match self.terminal.read_key() {
+                Ok(Some(Key::Char('\n'))) => {
+                    picked = Some(cmp::min(self.cursor_pos.y, self.buffers.len() - 1));
+                    break;
+                }
+                Ok(Some(key @ (Key::Up | Key::Down | Key::PageUp | Key::PageDown))) => {
+                    self.move_cursor(key);
+                    self.scroll();
+                }
+                Ok(None) => self.handle_resize(),
+                Ok(Some(Key::Esc)) | Err(_) => break,
+                _ => (),
+            }
+        }
+        self.exchange_buffer(&mut list);
+        self.scroll();
+        self.status_msg = StatusMessage::from(String::new());
+        if let Some(index) = picked {
+            self.switch_buffer(index);
+            self.show_buffer_name();
+        }
+    }
+
+    // Opens a file named at a prompt in a new buffer, or switches to it if
+    // it is already open.
+    fn open_file(&mut self) {
+        let file_name = match self.prompt("Open file: ", |_, _, _| {}) {
+            Ok(Some(file_name)) => file_name,
+            _ => {
+                self.status_msg = StatusMessage::from("Open aborted.".to_string());
+                return;
+            }
+        };
+        let (file_name, location) = split_location(&file_name);
+        let open = (0..self.buffers.len())
+            .find(|index| self.buffer_document(*index).filename.as_deref() == Some(&file_name));
+        if let Some(index) = open {
+            self.switch_buffer(index);
+            self.show_buffer_name();
+        } else {
+            let document = match Document::open(&file_name) {
+                Ok(document) => document,
+                Err(error) => {
+                    self.status_msg = StatusMessage::from(format!(
+                        "ERR: Could not open file: {}: {}",
+                        file_name, error
+                    ));
+                    return;
+                }
+            };
+            let message = file_message(&document);
+            self.buffers.push(Buffer::from(document));
+            self.switch_buffer(self.buffers.len() - 1);
+            self.show_buffer_name();
+            if let Some(message) = message {
+                self.status_msg = StatusMessage::from(message);
+            }
+            self.check_swap();
+        }
+        if let Some(location) = location {
+            self.go_to(&location);
+        }
+    }
+
+    fn remove_all_swaps(&mut self) {
+        for (index, buffer) in self.buffers.iter().enumerate() {
+            if index == self.current || buffer.swap_disabled {
+                continue;
+            }
+            if let Some(file_name) = &buffer.document.filename {
+                let _ = SwapFile::for_file(file_name).remove();
+            }
+        }
+        self.remove_swap();
+    }
+
+    // Keeps the swap file up to date with unsaved changes, writing it at
+    // most every `SWAP_INTERVAL` unless asked to write it `immediately`.
+    fn update_swap(&mut self, immediately: bool) {
+        // Swap files hold text, so changes made in hex aren't kept in one.
+        if self.swap_disabled
+            || self.should_quit
+            || !self.document.is_dirty()
+            || self.document.is_hex()
+        {
+            return;
+        }
+        let file_name = match &self.document.filename {
+            Some(file_name) => file_name,
+            None => return,
+        };
+        if !immediately && self.swap_written.map_or(false, |(time, _)| time.elapsed() < SWAP_INTERVAL) {
+            return;
+        }
+        let contents = self.document.contents();
+        let mut hasher = DefaultHasher::new();
+        contents.hash(&mut hasher);
+        let hash = hasher.finish();
+        if self.swap_written.map_or(false, |(_, written)| written == hash) {
+            return;
+        }
+        if let Err(error) = SwapFile::for_file(file_name).write(&contents) {
+            self.status_msg = StatusMessage::from(format!("Could not write swap file: {}", error));
+        }
+        self.swap_written = Some((Instant::now(), hash));
+    }
+
+    fn remove_swap(&mut self) {
+        if self.swap_disabled {
+            return;
+        }
+        if let Some(file_name) = &self.document.filename {
+            let _ = SwapFile::for_file(file_name).remove();
+        }
+        self.swap_written = None;
+    }
+
+    fn replace(&mut self) {
+        let query = match self.prompt("Replace (regex): ", |_, _, _| {}) {
+            Ok(Some(query)) => query,
+            _ => return,
+        };
+        let regex = match Regex::new(&query) {
+            Ok(regex) => regex,
+            Err(error) => {
+                self.status_msg = StatusMessage::from(format!("Invalid regex: {}", error));
+                return;
+            }
+        };
+        let replacement = match self.prompt_input("Replace with ($1 for groups): ", |_, _, _| {}) {
+            Ok(Some(replacement)) => replacement,
+            _ => {
+                self.status_msg = StatusMessage::from("Replace aborted.".to_string());
+                return;
+            }
+        };
+
+        let mut at = self.cursor_pos.clone();
+        let mut replace_all = false;
+        let mut count = 0;
+        // Everything replaced in one go is undone together.
+        self.document.begin_edit_group();
+        while let Some((start, end)) = self.document.find_regex(&regex, &at) {
+            self.cursor_pos = start.clone();
+            self.scroll();
+            if !replace_all {
+                self.status_msg = StatusMessage::from(
+                    "Replace this match? (y)es (n)o (a)ll (q)uit".to_string(),
+                );
+                if self.refresh_screen().is_err() {
+                    break;
+                }
+                match self.terminal.read_key() {
+                    Ok(Some(Key::Char('y'))) => (),
+                    Ok(Some(Key::Char('n'))) => {
+                        at = self.next_match_start(&start, end);
+                        continue;
+                    }
+                    Ok(Some(Key::Char('a'))) => replace_all = true,
+                    Ok(Some(Key::Char('q'))) | Ok(Some(Key::Esc)) | Err(_) => break,
+                    Ok(None) => {
+                        self.handle_resize();
+                        continue;
+                    }
+                    _ => continue,
+                }
+            }
+            match self.document.replace_regex(&regex, &start, &replacement) {
+                Some(after) => {
+                    count += 1;
+                    self.cursor_pos = after.clone();
+                    at = if end == start.x {
+                        self.next_match_start(&after, after.x)
+                    } else {
+                        after
+                    };
+                }
+                None => break,
+            }
+        }
+        self.document.end_edit_group();
+        self.scroll();
+        self.status_msg = StatusMessage::from(format!("Replaced {} occurrence(s).", count));
+    }
+
+    // Where to look for the next match so an empty match isn't found again.
+    fn next_match_start(&self, start: &Position, end: usize) -> Position {
+        if end > start.x {
+            return Position { x: end, y: start.y };
+        }
+        let len = self.document.row(start.y).map_or(0, Row::len);
+        if start.x < len {
+            Position {
+                x: start.x.saturating_add(1),
+                y: start.y,
+            }
+        } else {
+            Position {
+                x: 0,
+                y: start.y.saturating_add(1),
+            }
+        }
+    }
+
+    fn go_to_line(&mut self) {
+        let target = self
+            .prompt("Go to (line[:col], +N/-N, N%): ", |_, _, _| {})
+            .unwrap_or(None);
+        if let Some(target) = target {
+            if !self.go_to(&target) {
+                self.status_msg = StatusMessage::from(format!("Invalid location: {}", target));
+            }
+        }
+    }
+
+    /// Moves the cursor to a `go_to_line` style location and centers the
+    /// view on it. Returns false if the location can't be parsed.
+    fn go_to(&mut self, target: &str) -> bool {
+        self.finish_loading();
+        let position = match parse_location(target, &self.cursor_pos, self.document.len()) {
+            Some(position) => position,
+            None => return false,
+        };
+        let width = self.document.row(position.y).map_or(0, Row::len);
+        self.cursor_pos = Position {
+            x: cmp::min(position.x, width),
+            y: position.y,
+        };
+        self.selection = None;
+        let height = self.view_size().height as usize;
+        self.offset.y = self.cursor_pos.y.saturating_sub(height / 2);
+        self.wrap_offset = 0;
+        self.scroll();
+        true
+    }
 
     fn search(&mut self) {
         let old_position = self.cursor_pos.clone();
@@ -492,9 +2175,650 @@ impl Editor {
         //     }
         // }
     }
+
+    // Switches between editing the document as text and as its bytes, with
+    // the cursor back at the start.
+    fn toggle_hex(&mut self) {
+        self.finish_loading();
+        let hex = !self.document.is_hex();
+        let result = if hex {
+            self.document.show_hex()
+        } else {
+            self.document.show_text()
+        };
+        if let Err(error) = result {
+            self.status_msg = StatusMessage::from(format!(
+                "Could not show the file as {}: {}",
+                if hex { "hex" } else { "text" },
+                error
+            ));
+            return;
+        }
+        self.cursor_pos = Position::default();
+        self.offset = Position::default();
+        self.wrap_offset = 0;
+        self.hex_cursor = HexCursor::default();
+        self.selection = None;
+        self.status_msg = StatusMessage::from(if hex {
+            "Hex mode. Tab moves between the hex and text columns.".to_string()
+        } else {
+            "Text mode.".to_string()
+        });
+    }
+
+    // How many bytes each row of the hex view shows, as fit the pane.
+    fn hex_bytes_per_line(&self) -> usize {
+        hex::bytes_per_line(self.view_size().width as usize)
+    }
+
+    // The byte the cursor is on in the hex view.
+    fn hex_index(&self) -> usize {
+        self.hex_cursor.index
+    }
+
+    fn set_hex_index(&mut self, index: usize) {
+        self.hex_cursor.index = index;
+    }
+
+    fn move_hex_cursor(&mut self, key: Key) {
+        let last = self.document.bytes().len().saturating_sub(1);
+        let index = self.hex_index();
+        let per_line = self.hex_bytes_per_line();
+        let page = (self.view_size().height as usize).saturating_mul(per_line);
+        let line_start = index - index % per_line;
+        let index = match key {
+            // From the second hex digit, back to the first.
+            Key::Left if self.hex_cursor.low_nibble => index,
+            Key::Left => index.saturating_sub(1),
+            Key::Right => index.saturating_add(1),
+            Key::Up => index.checked_sub(per_line).unwrap_or(index),
+            Key::Down if index.saturating_add(per_line) <= last => index + per_line,
+            Key::PageUp => index.checked_sub(page).unwrap_or(index % per_line),
+            Key::PageDown => index.saturating_add(page),
+            Key::Home => line_start,
+            Key::End => line_start + per_line - 1,
+            _ => index,
+        };
+        self.hex_cursor.low_nibble = false;
+        self.set_hex_index(cmp::min(index, last));
+    }
+
+    // Overwrites the byte under the cursor, a hex digit at a time in the
+    // hex column or with an ASCII character in the text column. Tab moves
+    // between the two.
+    fn type_hex(&mut self, c: char) {
+        if c == '\t' {
+            self.hex_cursor = HexCursor {
+                text: !self.hex_cursor.text,
+                low_nibble: false,
+                ..self.hex_cursor
+            };
+            return;
+        }
+        let index = self.hex_index();
+        let byte = match self.document.bytes().get(index) {
+            Some(byte) => *byte,
+            None => return,
+        };
+        let byte = if self.hex_cursor.text {
+            if !c.is_ascii() || c.is_ascii_control() {
+                self.status_msg =
+                    StatusMessage::from("Only ASCII can be typed in the text column.".to_string());
+                return;
+            }
+            c as u8
+        } else {
+            let digit = match c.to_digit(16) {
+                Some(digit) => digit as u8,
+                None => {
+                    self.status_msg = StatusMessage::from(
+                        "Type a hex digit, or press Tab to type text.".to_string(),
+                    );
+                    return;
+                }
+            };
+            hex::set_nibble(byte, digit, self.hex_cursor.low_nibble)
+        };
+        self.document.set_byte(index, byte);
+        if self.hex_cursor.text || self.hex_cursor.low_nibble {
+            self.move_hex_cursor(Key::Right);
+        } else {
+            self.hex_cursor.low_nibble = true;
+        }
+    }
+
+    // Searches the bytes as the pattern is typed, like `search` does text.
+    fn search_bytes(&mut self) {
+        let old_index = self.hex_index();
+        let mut direction = SearchDirection::Forward;
+        let query = self
+            .prompt(
+                "Search bytes (hex or \"text\", Arrows to navigate): ",
+                |editor, key, query| {
+                    let mut at = editor.hex_index();
+                    match key {
+                        Key::Right | Key::Down => {
+                            direction = SearchDirection::Forward;
+                            at = at.saturating_add(1);
+                        }
+                        Key::Left | Key::Up => direction = SearchDirection::Backward,
+                        _ => (),
+                    }
+                    let found = hex::parse_pattern(query)
+                        .and_then(|pattern| hex::find(editor.document.bytes(), &pattern, at, direction));
+                    if let Some(index) = found {
+                        editor.hex_cursor.low_nibble = false;
+                        editor.set_hex_index(index);
+                        editor.scroll();
+                    }
+                },
+            )
+            .unwrap_or(None);
+        match query {
+            None => {
+                self.set_hex_index(old_index);
+                self.scroll();
+            }
+            Some(query) if hex::parse_pattern(&query).is_none() => {
+                self.status_msg = StatusMessage::from(format!(
+                    "Search for hex digits like 0d 0a, or \"text\" in quotes, not {}",
+                    query
+                ));
+            }
+            Some(_) => (),
+        }
+    }
+}
+
+/// Parses `N`, `N:C`, `+N`, `-N` or `N%` into a position, with lines and
+/// columns counted from 1 and clamped to the document's rows.
+fn parse_location(target: &str, current: &Position, len: usize) -> Option<Position> {
+    let target = target.trim();
+    let last = len.saturating_sub(1);
+    let (line, column) = match target.split_once(':') {
+        Some((line, column)) => (line, Some(column.parse::<usize>().ok()?)),
+        None => (target, None),
+    };
+    let y = if let Some(percent) = line.strip_suffix('%') {
+        let percent: usize = percent.parse().ok()?;
+        len.saturating_mul(cmp::min(percent, 100)) / 100
+    } else if let Some(lines) = line.strip_prefix('+') {
+        current.y.saturating_add(lines.parse().ok()?)
+    } else if let Some(lines) = line.strip_prefix('-') {
+        current.y.saturating_sub(lines.parse().ok()?)
+    } else {
+        line.parse::<usize>().ok()?.saturating_sub(1)
+    };
+    Some(Position {
+        x: column.map_or(0, |column| column.saturating_sub(1)),
+        y: cmp::min(y, last),
+    })
+}
+
+/// Splits a command line argument like `src/main.rs:12:5` into the file name
+/// and the location after it. A file that exists under the full name wins.
+fn split_location(arg: &str) -> (String, Option<String>) {
+    if Path::new(arg).exists() {
+        return (arg.to_string(), None);
+    }
+    let is_number = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit());
+    let parts: Vec<&str> = arg.rsplitn(3, ':').collect();
+    match parts.as_slice() {
+        [column, line, file] if is_number(column) && is_number(line) => {
+            (file.to_string(), Some(format!("{}:{}", line, column)))
+        }
+        [line, rest @ ..] if is_number(line) && !rest.is_empty() => {
+            let file = arg[..arg.len() - line.len() - 1].to_string();
+            (file, Some(line.to_string()))
+        }
+        _ => (arg.to_string(), None),
+    }
+}
+
+// What to tell the user about `document` that is down to the file itself:
+// that it can't be changed, other than while it loads, or that some of it
+// isn't valid text.
+fn file_message(document: &Document) -> Option<String> {
+    let file_name = buffer_name(document);
+    if document.is_truncated() {
+        Some(format!(
+            "{} is too large to edit, so it is read-only and only its first {} bytes are shown.",
+            file_name,
+            document.bytes().len()
+        ))
+    } else if document.is_large() {
+        Some(format!(
+            "{} is too large to edit, so it is read-only and not highlighted.",
+            file_name
+        ))
+    } else if document.has_raw_bytes() {
+        Some(format!(
+            "{} isn't all valid {}. Bytes shown as � are saved unchanged; Alt-C reopens it as another encoding.",
+            file_name,
+            document.encoding().encoding().name()
+        ))
+    } else {
+        None
+    }
 }
 
-fn die(e: &std::io::Error) {
-    Terminal::clear_screen();
+// Whether `key` changes the document or saves it.
+fn edits(key: Key) -> bool {
+    matches!(
+        key,
+        Key::Char(_)
+            | Key::Backspace
+            | Key::Delete
+            | Key::Ctrl('s' | 'r' | 'x' | 'v' | 'z' | 'y')
+            | Key::Alt('y' | 'e')
+    )
+}
+
+// Whether `key` only makes sense for text, so does nothing in hex mode.
+fn text_only(key: Key) -> bool {
+    matches!(
+        key,
+        Key::Backspace
+            | Key::Delete
+            | Key::Null
+            | Key::Ctrl('r' | 'x' | 'c' | 'v' | 'z' | 'y' | 'g')
+            | Key::Alt('y' | 'e')
+    )
+}
+
+fn buffer_name(document: &Document) -> String {
+    document
+        .filename
+        .clone()
+        .unwrap_or_else(|| "[No Name]".to_string())
+}
+
+fn die(terminal: &mut dyn Backend, e: &std::io::Error) {
+    terminal.clear_screen();
     panic!("{}", e);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::virtual_screen::VirtualScreen;
+    use std::{fs, process};
+
+    // An editor drawing to a `width` by `height` virtual screen, started
+    // with `args` as its command line arguments.
+    fn editor(args: &[&str], width: usize, height: usize) -> (Editor, VirtualScreen) {
+        let screen = VirtualScreen::new(width, height);
+        let mut command_line = vec!["hecto".to_string()];
+        command_line.extend(args.iter().map(|arg| arg.to_string()));
+        let mut editor = Editor::new(Box::new(screen.clone()), &command_line);
+        editor.system_clipboard = SystemClipboard::Osc52;
+        editor.refresh_screen().unwrap();
+        (editor, screen)
+    }
+
+    // Handles every queued key, redrawing after each one as `run` does.
+    fn drive(editor: &mut Editor) {
+        while editor.terminal.input_pending().unwrap() {
+            editor.process_keypress().unwrap();
+            editor.refresh_screen().unwrap();
+        }
+    }
+
+    fn press(editor: &mut Editor, screen: &VirtualScreen, keys: &[Key]) {
+        screen.push_keys(keys);
+        drive(editor);
+    }
+
+    fn type_text(editor: &mut Editor, screen: &VirtualScreen, text: &str) {
+        screen.type_text(text);
+        drive(editor);
+    }
+
+    fn cursor(screen: &VirtualScreen) -> (usize, usize) {
+        let Position { x, y } = screen.cursor();
+        (x, y)
+    }
+
+    #[test]
+    fn typed_text_is_drawn() {
+        let (mut editor, screen) = editor(&[], 40, 6);
+        type_text(&mut editor, &screen, "hello\nworld");
+        assert_eq!(screen.line(0), "hello");
+        assert_eq!(screen.line(1), "world");
+        assert_eq!(screen.line(2), "~");
+        assert!(screen.line(4).starts_with("[No Name] - 2 lines (modeified)"));
+        assert_eq!(cursor(&screen), (5, 1));
+    }
+
+    #[test]
+    fn empty_document_shows_welcome() {
+        let (_, screen) = editor(&[], 40, 8);
+        let lines = screen.lines();
+        assert_eq!(lines[0], "~");
+        assert!(lines[2].starts_with("~") && lines[2].contains("Hecto editor -- version"));
+        assert!(lines[7].starts_with("HELP:"));
+    }
+
+    #[test]
+    fn shift_arrows_select_for_copy() {
+        let (mut editor, screen) = editor(&[], 40, 6);
+        type_text(&mut editor, &screen, "abc");
+        screen.push_key(Key::Home);
+        for _ in 0..2 {
+            screen.push_input(Input {
+                key: Key::Right,
+                shift: true,
+            });
+        }
+        press(&mut editor, &screen, &[Key::Ctrl('c')]);
+        assert_eq!(screen.clipboard().as_deref(), Some("YWI="));
+        assert_eq!(screen.line(5), "Copied.");
+    }
+
+    #[test]
+    fn redraws_at_new_size() {
+        let (mut editor, screen) = editor(&[], 40, 6);
+        type_text(&mut editor, &screen, "text");
+        screen.resize(30, 8);
+        drive(&mut editor);
+        assert_eq!(screen.line(0), "text");
+        assert!(screen.line(6).starts_with("[No Name] - 1 lines"));
+        assert_eq!(screen.line(6).chars().count(), 30);
+    }
+
+    #[test]
+    fn shrinking_scrolls_the_cursor_back_into_view() {
+        let (mut editor, screen) = editor(&[], 40, 12);
+        let lines: Vec<String> = (1..=9).map(|line| format!("line {}", line)).collect();
+        type_text(&mut editor, &screen, &lines.join("\n"));
+        type_text(&mut editor, &screen, "\nthe last line is the longest");
+        assert_eq!(cursor(&screen), (28, 9));
+
+        screen.resize(20, 6);
+        drive(&mut editor);
+        assert_eq!(screen.lines()[..4], ["", "", "", "line is the longest"]);
+        assert_eq!(cursor(&screen), (19, 3));
+        assert!(screen.line(4).starts_with("[No Name] - 10"));
+        assert_eq!(screen.line(4).chars().count(), 20);
+
+        press(&mut editor, &screen, &[Key::Home]);
+        assert_eq!(screen.lines()[..4], ["line 7", "line 8", "line 9", "the last line is the"]);
+        assert_eq!(cursor(&screen), (0, 3));
+    }
+
+    #[test]
+    fn run_quits_on_ctrl_a() {
+        let (mut editor, screen) = editor(&[], 40, 6);
+        screen.push_keys(&[Key::Char('x'), Key::Ctrl('a'), Key::Ctrl('a'), Key::Ctrl('a'), Key::Ctrl('a')]);
+        editor.run();
+        assert_eq!(screen.line(0), "Goodbye!!!");
+    }
+
+    fn select_right(screen: &VirtualScreen, count: usize) {
+        for _ in 0..count {
+            screen.push_input(Input {
+                key: Key::Right,
+                shift: true,
+            });
+        }
+    }
+
+    #[test]
+    fn typing_over_a_selection_undoes_in_one_step() {
+        let (mut editor, screen) = editor(&[], 40, 6);
+        type_text(&mut editor, &screen, "abcd");
+        screen.push_keys(&[Key::Home, Key::Right]);
+        select_right(&screen, 2);
+        type_text(&mut editor, &screen, "XY");
+        assert_eq!(editor.document.contents(), "aXYd");
+        press(&mut editor, &screen, &[Key::Ctrl('z')]);
+        assert_eq!(editor.document.contents(), "abcd");
+        press(&mut editor, &screen, &[Key::Ctrl('y')]);
+        assert_eq!(editor.document.contents(), "aXYd");
+    }
+
+    #[test]
+    fn pasting_over_a_selection_undoes_in_one_step() {
+        let (mut editor, screen) = editor(&[], 40, 6);
+        type_text(&mut editor, &screen, "abcd");
+        screen.push_key(Key::Home);
+        select_right(&screen, 1);
+        press(&mut editor, &screen, &[Key::Ctrl('c'), Key::End]);
+        select_right(&screen, 0);
+        screen.push_keys(&[Key::Left, Key::Left]);
+        select_right(&screen, 2);
+        press(&mut editor, &screen, &[Key::Ctrl('v')]);
+        assert_eq!(editor.document.contents(), "aba");
+        press(&mut editor, &screen, &[Key::Ctrl('z')]);
+        assert_eq!(editor.document.contents(), "abcd");
+    }
+
+    #[test]
+    fn tabbing_over_a_selection_undoes_in_one_step() {
+        let (mut editor, screen) = editor(&[], 40, 6);
+        editor.tabs.insert_spaces = true;
+        type_text(&mut editor, &screen, "abcd");
+        screen.push_keys(&[Key::Home, Key::Right]);
+        select_right(&screen, 2);
+        press(&mut editor, &screen, &[Key::Char('\t')]);
+        assert_eq!(screen.line(0), format!("a{}d", " ".repeat(editor.tabs.width - 1)));
+        press(&mut editor, &screen, &[Key::Ctrl('z')]);
+        assert_eq!(editor.document.contents(), "abcd");
+    }
+
+    #[test]
+    fn undoing_a_yank_pop_brings_back_the_first_yank() {
+        let (mut editor, screen) = editor(&[], 40, 6);
+        type_text(&mut editor, &screen, "one two");
+        screen.push_key(Key::Home);
+        select_right(&screen, 4);
+        press(&mut editor, &screen, &[Key::Ctrl('x')]);
+        select_right(&screen, 3);
+        press(&mut editor, &screen, &[Key::Ctrl('x')]);
+        assert_eq!(editor.document.contents(), "");
+        press(&mut editor, &screen, &[Key::Ctrl('v')]);
+        assert_eq!(editor.document.contents(), "two");
+        press(&mut editor, &screen, &[Key::Alt('y')]);
+        assert_eq!(editor.document.contents(), "one ");
+        press(&mut editor, &screen, &[Key::Ctrl('z')]);
+        assert_eq!(editor.document.contents(), "two");
+        press(&mut editor, &screen, &[Key::Ctrl('z')]);
+        assert_eq!(editor.document.contents(), "");
+    }
+
+    // Replaces every match of `regex` from the top with `replacement`.
+    fn replace_all(editor: &mut Editor, screen: &VirtualScreen, regex: &str, replacement: &str) {
+        screen.push_keys(&[Key::Ctrl('g')]);
+        screen.type_text("1\n");
+        screen.push_key(Key::Ctrl('r'));
+        screen.type_text(&format!("{}\n{}\na", regex, replacement));
+        drive(editor);
+    }
+
+    #[test]
+    fn empty_matches_are_each_replaced_once() {
+        for (regex, replacement, expected) in [
+            ("^", "> ", "> ab\n> cd"),
+            (r"\b", "|", "|ab|\n|cd|"),
+            ("x*", "-", "-a-b-\n-c-d-"),
+        ] {
+            let (mut editor, screen) = editor(&[], 40, 6);
+            type_text(&mut editor, &screen, "ab\ncd");
+            replace_all(&mut editor, &screen, regex, replacement);
+            assert_eq!(editor.document.contents(), expected, "replacing {:?}", regex);
+        }
+    }
+
+    #[test]
+    fn replacing_all_undoes_in_one_step() {
+        let (mut editor, screen) = editor(&[], 40, 6);
+        type_text(&mut editor, &screen, "a1 b2\nc3");
+        replace_all(&mut editor, &screen, r"(\w)(\d)", "$2$1");
+        assert_eq!(editor.document.contents(), "1a 2b\n3c");
+        assert!(screen.line(5).contains("Replaced 3"));
+        press(&mut editor, &screen, &[Key::Ctrl('z')]);
+        assert_eq!(editor.document.contents(), "a1 b2\nc3");
+        press(&mut editor, &screen, &[Key::Ctrl('y')]);
+        assert_eq!(editor.document.contents(), "1a 2b\n3c");
+    }
+
+    // A file in the temp directory that no other test uses.
+    fn temp_file(name: &str, bytes: &[u8]) -> String {
+        let path = env::temp_dir().join(format!("hecto-{}-{}", process::id(), name));
+        fs::write(&path, bytes).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn hex_edits_save_the_exact_bytes() {
+        let path = temp_file("hex-edit.bin", b"\x00\x01\x02\xff");
+        let (mut editor, screen) = editor(&[&path], 80, 6);
+        assert!(editor.document.is_hex());
+        assert_eq!(cursor(&screen), (10, 0));
+        press(&mut editor, &screen, &[Key::Right, Key::Char('4')]);
+        assert_eq!(cursor(&screen), (14, 0));
+        press(&mut editor, &screen, &[Key::Char('2'), Key::Char('\t'), Key::Char('Z')]);
+        assert_eq!(cursor(&screen), (64, 0));
+        assert!(screen.line(0).starts_with("00000000  00 42 5a ff"));
+        assert!(screen.line(0).ends_with("|.BZ.            |"));
+        press(&mut editor, &screen, &[Key::Ctrl('s')]);
+        assert_eq!(fs::read(&path).unwrap(), b"\x00\x42Z\xff");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn hex_lines_fit_the_pane() {
+        let path = temp_file("hex-narrow.bin", b"\x00abcdefghi");
+        let (mut editor, screen) = editor(&[&path], 40, 6);
+        assert_eq!(screen.line(0), "00000000  00 61  62 63  |.abc|");
+        assert_eq!(screen.line(2), "00000008  68 69         |hi  |");
+        press(&mut editor, &screen, &[Key::Down, Key::End]);
+        assert_eq!(cursor(&screen), (20, 1));
+
+        screen.resize(80, 6);
+        drive(&mut editor);
+        assert!(screen.line(0).ends_with("|.abcdefghi      |"));
+        assert_eq!(cursor(&screen), (31, 0));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn each_pane_keeps_its_hex_cursor() {
+        let path = temp_file("hex-panes.bin", b"\x00abcdefghi");
+        let (mut editor, screen) = editor(&[&path], 80, 10);
+        press(&mut editor, &screen, &[Key::Alt('s'), Key::Right, Key::Right, Key::Char('\t')]);
+        assert_eq!(editor.hex_index(), 2);
+        press(&mut editor, &screen, &[Key::Alt('o')]);
+        assert_eq!(editor.hex_index(), 0);
+        assert!(!editor.hex_cursor.text);
+        press(&mut editor, &screen, &[Key::End, Key::Alt('o')]);
+        assert_eq!(editor.hex_index(), 2);
+        assert!(editor.hex_cursor.text);
+        press(&mut editor, &screen, &[Key::Alt('o')]);
+        assert_eq!(editor.hex_index(), 9);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reloading_keeps_the_cursor_past_the_first_piece() {
+        let lines: Vec<String> = (0..50_000).map(|line| format!("line {:>24}", line)).collect();
+        let path = temp_file("reload-large.txt", lines.join("\n").as_bytes());
+        let (mut editor, screen) = editor(&[&path], 40, 6);
+        editor.finish_loading();
+        editor.cursor_pos = Position { x: 3, y: 45_000 };
+        press(&mut editor, &screen, &[Key::Alt('r')]);
+        assert!(!editor.document.is_loading());
+        assert_eq!((editor.cursor_pos.x, editor.cursor_pos.y), (3, 45_000));
+        assert_eq!(screen.line(3), format!("line {:>24}", 45_000));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn other_panes_follow_lines_added_and_removed_above() {
+        let (mut editor, screen) = editor(&[], 40, 12);
+        type_text(&mut editor, &screen, "l0\nl1\nl2\nl3\nl4");
+        press(&mut editor, &screen, &[Key::Alt('s'), Key::Up, Key::Up, Key::Up, Key::Up, Key::Home]);
+        press(&mut editor, &screen, &[Key::Char('\n'), Key::Char('\n')]);
+        press(&mut editor, &screen, &[Key::Alt('o')]);
+        assert_eq!((editor.cursor_pos.x, editor.cursor_pos.y), (2, 6));
+        assert_eq!(editor.document.row(6).unwrap().substring(0, 2), "l4");
+
+        press(&mut editor, &screen, &[Key::Up, Key::Up, Key::Up, Key::Up, Key::Up, Key::Up, Key::Delete]);
+        press(&mut editor, &screen, &[Key::Alt('o')]);
+        assert_eq!((editor.cursor_pos.x, editor.cursor_pos.y), (0, 1));
+        assert_eq!(editor.document.row(1).unwrap().substring(0, 2), "l0");
+    }
+
+    #[test]
+    fn panes_are_arranged_when_the_layout_changes() {
+        let (mut editor, screen) = editor(&[], 41, 12);
+        assert_eq!(editor.view_size().width, 41);
+        press(&mut editor, &screen, &[Key::Alt('v')]);
+        assert_eq!((editor.view_size().width, editor.view_size().height), (20, 10));
+        press(&mut editor, &screen, &[Key::Alt('+')]);
+        assert_eq!(editor.view_size().width, 22);
+        screen.resize(81, 12);
+        drive(&mut editor);
+        assert_eq!(editor.view_size().width, 44);
+        press(&mut editor, &screen, &[Key::Alt('q')]);
+        assert_eq!(editor.view_size().width, 81);
+    }
+
+    #[test]
+    fn locations_are_parsed() {
+        let current = Position { x: 5, y: 10 };
+        let cases: [(&str, Option<(usize, usize)>); 22] = [
+            ("1", Some((0, 0))),
+            ("42", Some((0, 41))),
+            (" 7 ", Some((0, 6))),
+            ("0", Some((0, 0))),
+            ("500", Some((0, 99))),
+            ("3:4", Some((3, 2))),
+            ("3:0", Some((0, 2))),
+            ("+5", Some((0, 15))),
+            ("+200", Some((0, 99))),
+            ("-3", Some((0, 7))),
+            ("-30", Some((0, 0))),
+            ("+2:8", Some((7, 12))),
+            ("50%", Some((0, 50))),
+            ("0%", Some((0, 0))),
+            ("100%", Some((0, 99))),
+            ("150%", Some((0, 99))),
+            ("3:x", None),
+            ("abc", None),
+            ("", None),
+            ("%", None),
+            ("+", None),
+            ("-x", None),
+        ];
+        for (target, expected) in cases {
+            let position = parse_location(target, &current, 100).map(|Position { x, y }| (x, y));
+            assert_eq!(position, expected, "parsing {:?}", target);
+        }
+        let position = parse_location("5:3", &current, 0).map(|Position { x, y }| (x, y));
+        assert_eq!(position, Some((2, 0)));
+    }
+
+    #[test]
+    fn locations_are_split_from_file_names() {
+        let cases = [
+            ("src/main.rs:12:5", "src/main.rs", Some("12:5")),
+            ("notes.txt:12", "notes.txt", Some("12")),
+            ("notes.txt", "notes.txt", None),
+            ("notes.txt:", "notes.txt:", None),
+            ("notes.txt:x", "notes.txt:x", None),
+            ("a:b:3", "a:b", Some("3")),
+            ("a:2:3", "a", Some("2:3")),
+            ("12", "12", None),
+        ];
+        for (arg, file, location) in cases {
+            let (split_file, split_location) = split_location(arg);
+            assert_eq!(split_file, file, "splitting {:?}", arg);
+            assert_eq!(split_location.as_deref(), location, "splitting {:?}", arg);
+        }
+
+        let path = temp_file("named:3", b"");
+        assert_eq!(split_location(&path), (path.clone(), None));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,344 @@
+use encoding_rs::{Decoder, Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use std::io::{Error, ErrorKind};
+
+// Bytes that aren't valid in a file's encoding are kept in its text as the
+// last 256 characters of the last private use plane, which files don't use
+// in practice, so saving can write them back as they were.
+const RAW_BYTE_BASE: u32 = 0x10_ff00;
+
+/// The character that stands for `byte` where it isn't valid text.
+pub fn raw_byte_char(byte: u8) -> char {
+    char::from_u32(RAW_BYTE_BASE + u32::from(byte)).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
+/// The byte `c` stands for, if it is one kept from an invalid sequence.
+pub fn raw_byte(c: char) -> Option<u8> {
+    u32::from(c).checked_sub(RAW_BYTE_BASE).and_then(|byte| u8::try_from(byte).ok())
+}
+
+/// `text` with each real character that would be taken for a raw byte
+/// replaced by raw byte characters for how `encoding` writes it, so it is
+/// saved as itself; `None` if there are no such characters. One the
+/// encoding can't write becomes a replacement character.
+pub fn escape_raw_range(encoding: &'static Encoding, text: &str) -> Option<String> {
+    if !text.chars().any(|c| raw_byte(c).is_some()) {
+        return None;
+    }
+    let file_encoding = FileEncoding {
+        encoding,
+        bom: false,
+    };
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if raw_byte(c).is_none() {
+            escaped.push(c);
+            continue;
+        }
+        let mut bytes = Vec::new();
+        match file_encoding.encode_text(c.encode_utf8(&mut [0; 4]), &mut bytes) {
+            Ok(()) => escaped.extend(bytes.into_iter().map(raw_byte_char)),
+            Err(_) => escaped.push(char::REPLACEMENT_CHARACTER),
+        }
+    }
+    Some(escaped)
+}
+
+/// The character encoding a file is stored in, and whether it starts with
+/// a byte order mark, so it can be written back the way it was read.
+#[derive(Clone, Copy, PartialEq)]
+pub struct FileEncoding {
+    encoding: &'static Encoding,
+    bom: bool,
+}
+
+impl Default for FileEncoding {
+    fn default() -> Self {
+        Self {
+            encoding: UTF_8,
+            bom: false,
+        }
+    }
+}
+
+impl FileEncoding {
+    /// Guesses the encoding from the start of a file: a byte order mark if
+    /// there is one, then UTF-16 if every other byte is zero, then UTF-8
+    /// unless more of it is invalid than valid, and otherwise
+    /// windows-1252, which can decode any bytes. `complete` says whether
+    /// `bytes` is the whole file rather than just its start.
+    pub fn detect(bytes: &[u8], complete: bool) -> Self {
+        if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+            return Self {
+                encoding,
+                bom: true,
+            };
+        }
+        let encoding = if let Some(encoding) = utf16_without_bom(bytes) {
+            encoding
+        } else {
+            let (multibyte, invalid) = utf8_evidence(bytes, complete);
+            if invalid == 0 || multibyte > invalid {
+                UTF_8
+            } else {
+                WINDOWS_1252
+            }
+        };
+        Self {
+            encoding,
+            bom: false,
+        }
+    }
+
+    /// `encoding`, chosen by the user for a file starting with `bytes`. A
+    /// byte order mark is only taken as one if it is for that encoding.
+    pub fn with_encoding(encoding: &'static Encoding, bytes: &[u8]) -> Self {
+        Self {
+            encoding,
+            bom: Encoding::for_bom(bytes).map_or(false, |(found, _)| found == encoding),
+        }
+    }
+
+    /// Whether a file starting with `bytes` looks like binary data rather
+    /// than text, by having zero bytes where text in this encoding doesn't.
+    pub fn is_binary(self, bytes: &[u8]) -> bool {
+        self.encoding != UTF_16LE && self.encoding != UTF_16BE && bytes.contains(&0)
+    }
+
+    /// The encoding named by `label`, such as "latin1" or "utf-16le".
+    pub fn for_label(label: &str) -> Option<&'static Encoding> {
+        Encoding::for_label(label.trim().as_bytes())
+    }
+
+    pub fn name(self) -> String {
+        if self.bom {
+            format!("{} BOM", self.encoding.name())
+        } else {
+            self.encoding.name().to_string()
+        }
+    }
+
+    pub fn encoding(self) -> &'static Encoding {
+        self.encoding
+    }
+
+    /// A decoder for the file's bytes, which skips the byte order mark.
+    pub fn new_decoder(self) -> Decoder {
+        if self.bom {
+            self.encoding.new_decoder_with_bom_removal()
+        } else {
+            self.encoding.new_decoder_without_bom_handling()
+        }
+    }
+
+    /// Encodes `text` to be written to the file, failing if it has
+    /// characters the encoding can't represent. Raw byte characters are
+    /// written as the bytes they stand for.
+    pub fn encode(self, text: &str) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::new();
+        if self.bom {
+            bytes.extend_from_slice(bom(self.encoding));
+        }
+        let mut rest = text;
+        while let Some((at, byte)) = rest.char_indices().find_map(|(at, c)| Some((at, raw_byte(c)?))) {
+            self.encode_text(&rest[..at], &mut bytes)?;
+            bytes.push(byte);
+            rest = &rest[at + raw_byte_char(byte).len_utf8()..];
+        }
+        self.encode_text(rest, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn encode_text(self, text: &str, bytes: &mut Vec<u8>) -> Result<(), Error> {
+        // encoding_rs only decodes UTF-16, writing UTF-8 instead.
+        if self.encoding == UTF_16LE {
+            text.encode_utf16().for_each(|unit| bytes.extend_from_slice(&unit.to_le_bytes()));
+        } else if self.encoding == UTF_16BE {
+            text.encode_utf16().for_each(|unit| bytes.extend_from_slice(&unit.to_be_bytes()));
+        } else {
+            let (encoded, _, unmappable) = self.encoding.encode(text);
+            if unmappable {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("some characters can't be written as {}", self.encoding.name()),
+                ));
+            }
+            bytes.extend_from_slice(&encoded);
+        }
+        Ok(())
+    }
+}
+
+fn bom(encoding: &'static Encoding) -> &'static [u8] {
+    if encoding == UTF_16LE {
+        b"\xff\xfe"
+    } else if encoding == UTF_16BE {
+        b"\xfe\xff"
+    } else if encoding == UTF_8 {
+        b"\xef\xbb\xbf"
+    } else {
+        b""
+    }
+}
+
+// Text that is mostly ASCII has a zero byte in every other place in UTF-16,
+// after each character in little-endian order and before it in big-endian.
+fn utf16_without_bom(bytes: &[u8]) -> Option<&'static Encoding> {
+    let pairs = bytes.len() / 2;
+    if pairs < 2 {
+        return None;
+    }
+    let even_zeros = bytes.iter().step_by(2).filter(|byte| **byte == 0).count();
+    let odd_zeros = bytes.iter().skip(1).step_by(2).filter(|byte| **byte == 0).count();
+    if odd_zeros * 2 > pairs && even_zeros * 10 < pairs {
+        Some(UTF_16LE)
+    } else if even_zeros * 2 > pairs && odd_zeros * 10 < pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+// Counts the multi-byte characters and the invalid sequences in `bytes`
+// taken as UTF-8. A sequence cut off at the end only counts as invalid if
+// `bytes` is complete.
+fn utf8_evidence(bytes: &[u8], complete: bool) -> (usize, usize) {
+    let multibyte = |text: &str| text.chars().filter(|c| c.len_utf8() > 1).count();
+    let mut found = 0;
+    let mut invalid = 0;
+    let mut rest = bytes;
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(text) => return (found + multibyte(text), invalid),
+            Err(error) => {
+                let (valid, after) = rest.split_at(error.valid_up_to());
+                found += std::str::from_utf8(valid).map_or(0, multibyte);
+                match error.error_len() {
+                    Some(len) => {
+                        invalid += 1;
+                        rest = &after[len..];
+                    }
+                    None => {
+                        if complete {
+                            invalid += 1;
+                        }
+                        return (found, invalid);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{SHIFT_JIS, WINDOWS_1251};
+
+    fn detected(bytes: &[u8], complete: bool) -> String {
+        FileEncoding::detect(bytes, complete).name()
+    }
+
+    fn decode(encoding: FileEncoding, bytes: &[u8]) -> String {
+        let mut decoder = encoding.new_decoder();
+        let mut text = String::with_capacity(decoder.max_utf8_buffer_length(bytes.len()).unwrap());
+        let (_, _, errors) = decoder.decode_to_string(bytes, &mut text, true);
+        assert!(!errors);
+        text
+    }
+
+    #[test]
+    fn byte_order_marks_are_detected() {
+        assert_eq!(detected(b"\xef\xbb\xbfplain", true), "UTF-8 BOM");
+        assert_eq!(detected(b"\xff\xfea\x00b\x00", true), "UTF-16LE BOM");
+        assert_eq!(detected(b"\xfe\xff\x00a\x00b", true), "UTF-16BE BOM");
+    }
+
+    #[test]
+    fn utf16_without_a_bom_is_detected() {
+        let text = "plain text, ✓";
+        let le: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let be: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        assert_eq!(detected(&le, true), "UTF-16LE");
+        assert_eq!(detected(&be, true), "UTF-16BE");
+        assert!(!FileEncoding::detect(&le, true).is_binary(&le));
+        assert_eq!(detected(b"a\x00", true), "UTF-8");
+    }
+
+    #[test]
+    fn utf8_and_latin1_are_told_apart() {
+        assert_eq!(detected(b"plain ascii", true), "UTF-8");
+        assert_eq!(detected("caf\u{e9} cr\u{e8}me".as_bytes(), true), "UTF-8");
+        assert_eq!(detected(b"caf\xe9 cr\xe8me", true), "windows-1252");
+        // Mostly UTF-8, with one stray byte.
+        assert_eq!(detected(b"caf\xc3\xa9 cr\xc3\xa8me \xff", true), "UTF-8");
+    }
+
+    #[test]
+    fn sequence_cut_off_at_the_end_of_a_piece() {
+        let cut = b"abc\xe2\x9c";
+        assert_eq!(detected(cut, false), "UTF-8");
+        assert_eq!(detected(cut, true), "windows-1252");
+        assert_eq!(utf8_evidence(cut, false), (0, 0));
+        assert_eq!(utf8_evidence(b"\xe2\x9c\x93\xff", true), (1, 1));
+    }
+
+    #[test]
+    fn chosen_encoding_only_takes_its_own_bom() {
+        assert_eq!(FileEncoding::with_encoding(UTF_8, b"\xef\xbb\xbfa").name(), "UTF-8 BOM");
+        assert_eq!(FileEncoding::with_encoding(UTF_16LE, b"\xef\xbb\xbfa").name(), "UTF-16LE");
+        assert_eq!(FileEncoding::for_label(" latin1 "), Some(WINDOWS_1252));
+        assert_eq!(FileEncoding::for_label("no such encoding"), None);
+    }
+
+    #[test]
+    fn encoding_round_trips() {
+        let files: [&[u8]; 6] = [
+            b"plain\n",
+            "caf\u{e9} \u{2713}\n".as_bytes(),
+            b"\xef\xbb\xbfwith a bom\n",
+            b"\xff\xfea\x00\n\x00",
+            b"\x00a\x00\n",
+            b"caf\xe9\n",
+        ];
+        for bytes in files {
+            let encoding = FileEncoding::detect(bytes, true);
+            assert_eq!(encoding.encode(&decode(encoding, bytes)).unwrap(), bytes, "{}", encoding.name());
+        }
+        for (encoding, bytes) in [(WINDOWS_1251, &b"\xcf\xf0\xe8\xe2\xe5\xf2"[..]), (SHIFT_JIS, b"\x93\xfa\x96\x7b")] {
+            let encoding = FileEncoding::with_encoding(encoding, bytes);
+            assert_eq!(encoding.encode(&decode(encoding, bytes)).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn raw_bytes_are_written_back() {
+        assert_eq!(raw_byte(raw_byte_char(0xff)), Some(0xff));
+        assert_eq!(raw_byte('\u{fffd}'), None);
+        assert_eq!(raw_byte(char::MAX), Some(0xff));
+        let text = format!("a{}b{}", raw_byte_char(0xff), raw_byte_char(0x80));
+        assert_eq!(FileEncoding::default().encode(&text).unwrap(), b"a\xffb\x80");
+        let utf16 = FileEncoding::with_encoding(UTF_16LE, b"");
+        assert_eq!(utf16.encode(&text).unwrap(), b"a\x00\xffb\x00\x80");
+    }
+
+    #[test]
+    fn characters_like_raw_bytes_are_escaped() {
+        assert_eq!(escape_raw_range(UTF_8, "plain"), None);
+        let escaped = escape_raw_range(UTF_8, "a\u{10ff41}b").unwrap();
+        assert_eq!(escaped.chars().count(), 6);
+        assert_eq!(FileEncoding::default().encode(&escaped).unwrap(), "a\u{10ff41}b".as_bytes());
+        let escaped = escape_raw_range(UTF_16LE, "\u{10ff41}").unwrap();
+        assert_eq!(
+            FileEncoding::with_encoding(UTF_16LE, b"").encode(&escaped).unwrap(),
+            b"\xff\xdb\x41\xdf"
+        );
+        assert_eq!(escape_raw_range(WINDOWS_1252, "\u{10ff41}").unwrap(), "\u{fffd}");
+    }
+
+    #[test]
+    fn unmappable_characters_fail_to_encode() {
+        let latin1 = FileEncoding::with_encoding(WINDOWS_1252, b"");
+        assert!(latin1.encode("\u{2713}").is_err());
+        assert_eq!(latin1.encode("\u{20ac}").unwrap(), b"\x80");
+    }
+}
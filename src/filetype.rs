@@ -0,0 +1,220 @@
+use std::path::Path;
+
+pub struct FileType {
+    name: String,
+    hl_opts: HighlightingOptions,
+}
+
+#[derive(Default)]
+pub struct HighlightingOptions {
+    numbers: bool,
+    strings: &'static str,
+    multiline_strings: bool,
+    triple_quotes: bool,
+    raw_strings: bool,
+    characters: bool,
+    line_comment: Option<&'static str>,
+    block_comment: Option<(&'static str, &'static str)>,
+    headings: Option<char>,
+    keywords: &'static [&'static str],
+    data_types: &'static [&'static str],
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+    "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+    "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true",
+    "type", "unsafe", "use", "where", "while",
+];
+const RUST_TYPES: &[&str] = &[
+    "bool", "char", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64",
+    "u128", "usize", "f32", "f64", "str", "String", "Vec", "Option", "Result", "Box", "Some",
+    "None", "Ok", "Err",
+];
+const C_KEYWORDS: &[&str] = &[
+    "auto", "break", "case", "const", "continue", "default", "do", "else", "enum", "extern",
+    "for", "goto", "if", "inline", "register", "restrict", "return", "sizeof", "static",
+    "struct", "switch", "typedef", "union", "volatile", "while", "NULL", "#include",
+    "#define", "#ifdef", "#ifndef", "#endif", "#if", "#else", "#pragma",
+];
+const C_TYPES: &[&str] = &[
+    "char", "double", "float", "int", "long", "short", "signed", "unsigned", "void", "bool",
+    "size_t", "ssize_t", "int8_t", "int16_t", "int32_t", "int64_t", "uint8_t", "uint16_t",
+    "uint32_t", "uint64_t", "FILE",
+];
+const PYTHON_KEYWORDS: &[&str] = &[
+    "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+    "elif", "else", "except", "False", "finally", "for", "from", "global", "if", "import", "in",
+    "is", "lambda", "None", "nonlocal", "not", "or", "pass", "raise", "return", "self", "True",
+    "try", "while", "with", "yield",
+];
+const PYTHON_TYPES: &[&str] = &[
+    "bool", "bytes", "dict", "float", "frozenset", "int", "list", "object", "set", "str",
+    "tuple",
+];
+const TOML_KEYWORDS: &[&str] = &["true", "false"];
+
+impl Default for FileType {
+    fn default() -> Self {
+        Self {
+            name: String::from("No filetype"),
+            hl_opts: HighlightingOptions::default(),
+        }
+    }
+}
+
+impl FileType {
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn hightlighting_options(&self) -> &HighlightingOptions {
+        &self.hl_opts
+    }
+
+    pub fn from(file_name: &str) -> Self {
+        let extension = Path::new(file_name)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default();
+        let (name, hl_opts) = match extension {
+            "rs" => (
+                "Rust",
+                HighlightingOptions {
+                    numbers: true,
+                    strings: "\"",
+                    multiline_strings: true,
+                    triple_quotes: false,
+                    raw_strings: true,
+                    characters: true,
+                    line_comment: Some("//"),
+                    block_comment: Some(("/*", "*/")),
+                    headings: None,
+                    keywords: RUST_KEYWORDS,
+                    data_types: RUST_TYPES,
+                },
+            ),
+            "c" | "h" => (
+                "C",
+                HighlightingOptions {
+                    numbers: true,
+                    strings: "\"",
+                    multiline_strings: false,
+                    triple_quotes: false,
+                    raw_strings: false,
+                    characters: true,
+                    line_comment: Some("//"),
+                    block_comment: Some(("/*", "*/")),
+                    headings: None,
+                    keywords: C_KEYWORDS,
+                    data_types: C_TYPES,
+                },
+            ),
+            "py" => (
+                "Python",
+                HighlightingOptions {
+                    numbers: true,
+                    strings: "\"'",
+                    multiline_strings: false,
+                    triple_quotes: true,
+                    raw_strings: false,
+                    characters: false,
+                    line_comment: Some("#"),
+                    block_comment: None,
+                    headings: None,
+                    keywords: PYTHON_KEYWORDS,
+                    data_types: PYTHON_TYPES,
+                },
+            ),
+            "md" | "markdown" => (
+                "Markdown",
+                HighlightingOptions {
+                    numbers: false,
+                    strings: "`",
+                    multiline_strings: false,
+                    triple_quotes: true,
+                    raw_strings: false,
+                    characters: false,
+                    line_comment: None,
+                    block_comment: Some(("<!--", "-->")),
+                    headings: Some('#'),
+                    keywords: &[],
+                    data_types: &[],
+                },
+            ),
+            "toml" => (
+                "TOML",
+                HighlightingOptions {
+                    numbers: true,
+                    strings: "\"'",
+                    multiline_strings: false,
+                    triple_quotes: true,
+                    raw_strings: false,
+                    characters: false,
+                    line_comment: Some("#"),
+                    block_comment: None,
+                    headings: Some('['),
+                    keywords: TOML_KEYWORDS,
+                    data_types: &[],
+                },
+            ),
+            _ => return Self::default(),
+        };
+        Self {
+            name: String::from(name),
+            hl_opts,
+        }
+    }
+}
+
+impl HighlightingOptions {
+    pub fn numbers(&self) -> bool {
+        self.numbers
+    }
+
+    /// Characters that open and close a string literal.
+    pub fn strings(&self) -> &str {
+        self.strings
+    }
+
+    /// Whether an unterminated string carries on to the next line.
+    pub fn multiline_strings(&self) -> bool {
+        self.multiline_strings
+    }
+
+    /// Whether a tripled string delimiter opens a string that may span lines,
+    /// like Python's `"""` or a Markdown code fence.
+    pub fn triple_quotes(&self) -> bool {
+        self.triple_quotes
+    }
+
+    pub fn raw_strings(&self) -> bool {
+        self.raw_strings
+    }
+
+    pub fn characters(&self) -> bool {
+        self.characters
+    }
+
+    pub fn line_comment(&self) -> Option<&str> {
+        self.line_comment
+    }
+
+    pub fn block_comment(&self) -> Option<(&str, &str)> {
+        self.block_comment
+    }
+
+    /// Lines starting with this character are highlighted as a whole, like
+    /// Markdown headings or TOML tables.
+    pub fn headings(&self) -> Option<char> {
+        self.headings
+    }
+
+    pub fn keywords(&self) -> &[&str] {
+        self.keywords
+    }
+
+    pub fn data_types(&self) -> &[&str] {
+        self.data_types
+    }
+}
@@ -0,0 +1,320 @@
+use crate::encoding;
+use crate::Position;
+use termion::color;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// One screen cell: the grapheme drawn in it and its colours, where `None`
+/// is the terminal's default colour. A wide grapheme is followed by cells
+/// with an empty symbol for the extra columns it covers.
+#[derive(Clone, PartialEq)]
+pub struct Cell {
+    pub symbol: String,
+    pub fg: Option<color::Rgb>,
+    pub bg: Option<color::Rgb>,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            symbol: String::from(" "),
+            fg: None,
+            bg: None,
+        }
+    }
+}
+
+impl Cell {
+    pub fn new(symbol: &str, fg: Option<color::Rgb>, bg: Option<color::Rgb>) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            fg,
+            bg,
+        }
+    }
+
+    /// The cells `grapheme` covers on screen; none if it has no width. A
+    /// byte that wasn't valid text, or a control character the terminal
+    /// would act on, is drawn as a replacement character.
+    pub fn for_grapheme(grapheme: &str, fg: Option<color::Rgb>, bg: Option<color::Rgb>) -> Vec<Self> {
+        let width = display_width(grapheme);
+        let mut cells = Vec::with_capacity(width);
+        if width > 0 {
+            let replaced = grapheme.chars().next().map_or(false, |c| {
+                (c.is_control() && c != '\t') || encoding::raw_byte(c).is_some()
+            });
+            cells.push(Self::new(if replaced { "\u{fffd}" } else { grapheme }, fg, bg));
+            cells.resize(width, Self::new("", fg, bg));
+        }
+        cells
+    }
+
+    fn is_continuation(&self) -> bool {
+        self.symbol.is_empty()
+    }
+}
+
+/// How many screen columns a grapheme takes up: two for East Asian wide
+/// characters, none for zero-width ones. Tabs are drawn as a single space.
+pub fn display_width(grapheme: &str) -> usize {
+    if grapheme == "\t" {
+        1
+    } else {
+        grapheme.width()
+    }
+}
+
+/// Everything on screen for one refresh. Drawing fills a fresh frame and
+/// `diff` turns it into the output needed to update the previous one.
+pub struct Frame {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+    cursor: Position,
+}
+
+impl Frame {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![Cell::default(); width.saturating_mul(height)],
+            cursor: Position::default(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Copies `other` onto this frame with its top left corner at `x`, `y`.
+    pub fn blit(&mut self, x: usize, y: usize, other: &Frame) {
+        for row in 0..other.height {
+            for column in 0..other.width {
+                let cell = other.cells[row * other.width + column].clone();
+                self.set(x.saturating_add(column), y.saturating_add(row), cell);
+            }
+        }
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, cell: Cell) {
+        if x < self.width && y < self.height {
+            self.cells[y * self.width + x] = cell;
+        }
+    }
+
+    /// Writes `cells` from column `x` of line `y`, clipped to the frame,
+    /// and returns the column after the last one written. A wide grapheme
+    /// that would be cut off by the right edge is replaced with a space.
+    pub fn put_cells(&mut self, x: usize, y: usize, cells: Vec<Cell>) -> usize {
+        let mut x = x;
+        for cell in cells {
+            if display_width(&cell.symbol) > self.width.saturating_sub(x) {
+                self.set(x, y, Cell::new(" ", cell.fg, cell.bg));
+            } else {
+                self.set(x, y, cell);
+            }
+            x = x.saturating_add(1);
+        }
+        x
+    }
+
+    /// Writes `text` in the given colours and returns the column after it.
+    pub fn put_str(
+        &mut self,
+        x: usize,
+        y: usize,
+        text: &str,
+        fg: Option<color::Rgb>,
+        bg: Option<color::Rgb>,
+    ) -> usize {
+        let cells = text
+            .graphemes(true)
+            .flat_map(|grapheme| Cell::for_grapheme(grapheme, fg, bg))
+            .collect();
+        self.put_cells(x, y, cells)
+    }
+
+    pub fn set_cursor(&mut self, pos: Position) {
+        self.cursor = pos;
+    }
+
+    /// Returns the escape sequences and text that turn `previous` into this
+    /// frame, moving the cursor only where the changed cells aren't
+    /// contiguous. Without a previous frame of the same size the screen is
+    /// cleared and drawn in full.
+    pub fn diff(&self, previous: Option<&Frame>) -> String {
+        let mut output = format!("{}", termion::cursor::Hide);
+        let previous = match previous {
+            Some(previous) if previous.width == self.width && previous.height == self.height => {
+                Some(previous)
+            }
+            _ => {
+                output.push_str(&format!("{}", termion::clear::All));
+                None
+            }
+        };
+
+        let blank = Cell::default();
+        let mut at: Option<(usize, usize)> = None;
+        let mut fg = None;
+        let mut bg = None;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let cell = &self.cells[y * self.width + x];
+                let old = previous.map_or(&blank, |previous| &previous.cells[y * self.width + x]);
+                // The terminal fills these in when it draws the wide
+                // grapheme before them.
+                if cell == old || cell.is_continuation() {
+                    continue;
+                }
+                if at != Some((x, y)) {
+                    output.push_str(&format!("{}", goto(x, y)));
+                }
+                if fg != Some(cell.fg) {
+                    fg = Some(cell.fg);
+                    match cell.fg {
+                        Some(rgb) => output.push_str(&format!("{}", color::Fg(rgb))),
+                        None => output.push_str(&format!("{}", color::Fg(color::Reset))),
+                    }
+                }
+                if bg != Some(cell.bg) {
+                    bg = Some(cell.bg);
+                    match cell.bg {
+                        Some(rgb) => output.push_str(&format!("{}", color::Bg(rgb))),
+                        None => output.push_str(&format!("{}", color::Bg(color::Reset))),
+                    }
+                }
+                output.push_str(&cell.symbol);
+                at = Some((x.saturating_add(display_width(&cell.symbol).max(1)), y));
+            }
+        }
+        output.push_str(&format!(
+            "{}{}{}{}",
+            color::Fg(color::Reset),
+            color::Bg(color::Reset),
+            goto(self.cursor.x, self.cursor.y),
+            termion::cursor::Show
+        ));
+        output
+    }
+}
+
+fn goto(x: usize, y: usize) -> termion::cursor::Goto {
+    termion::cursor::Goto(x.saturating_add(1) as u16, y.saturating_add(1) as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbols(cells: &[Cell]) -> Vec<&str> {
+        cells.iter().map(|cell| cell.symbol.as_str()).collect()
+    }
+
+    fn goto_str(x: usize, y: usize) -> String {
+        format!("{}", goto(x, y))
+    }
+
+    // What the first cell drawn in the default colours is preceded by.
+    fn reset() -> String {
+        format!("{}{}", color::Fg(color::Reset), color::Bg(color::Reset))
+    }
+
+    #[test]
+    fn unchanged_frames_write_nothing() {
+        let mut frame = Frame::new(6, 2);
+        frame.put_str(0, 0, "hello", None, None);
+        frame.set_cursor(Position { x: 2, y: 1 });
+        let expected = format!(
+            "{}{}{}{}{}",
+            termion::cursor::Hide,
+            color::Fg(color::Reset),
+            color::Bg(color::Reset),
+            goto_str(2, 1),
+            termion::cursor::Show
+        );
+        assert_eq!(frame.diff(Some(&frame)), expected);
+    }
+
+    #[test]
+    fn only_separated_changes_move_the_cursor() {
+        let previous = Frame::new(8, 2);
+        let mut frame = Frame::new(8, 2);
+        frame.put_str(0, 0, "ab", None, None);
+        frame.put_str(4, 0, "c", None, None);
+        frame.put_str(7, 0, "d", None, None);
+        frame.put_str(0, 1, "e", None, None);
+        let output = frame.diff(Some(&previous));
+        assert!(output.contains(&format!("{}{}ab{}c", goto_str(0, 0), reset(), goto_str(4, 0))));
+        // The terminal may not have wrapped yet after the last column.
+        assert!(output.contains(&format!("{}d{}e", goto_str(7, 0), goto_str(0, 1))));
+        assert_eq!(output.matches('H').count(), 5);
+        assert!(!output.contains(&format!("{}", termion::clear::All)));
+    }
+
+    #[test]
+    fn wide_graphemes_cover_their_continuation_cells() {
+        let previous = Frame::new(5, 1);
+        let mut frame = Frame::new(5, 1);
+        assert_eq!(frame.put_str(0, 0, "\u{4e16}a\u{754c}", None, None), 5);
+        let output = frame.diff(Some(&previous));
+        assert!(output.contains(&format!("{}{}\u{4e16}a\u{754c}{}", goto_str(0, 0), reset(), reset())));
+        assert_eq!(output.matches('H').count(), 2);
+
+        // A wide grapheme cut off by the right edge becomes a space, and
+        // what is past the edge is dropped.
+        let mut frame = Frame::new(3, 1);
+        assert_eq!(frame.put_str(1, 0, "a\u{4e16}b", None, None), 5);
+        assert_eq!(symbols(&frame.cells), [" ", "a", " "]);
+    }
+
+    #[test]
+    fn colours_change_only_between_runs() {
+        let red = Some(color::Rgb(255, 0, 0));
+        let previous = Frame::new(4, 1);
+        let mut frame = Frame::new(4, 1);
+        frame.put_str(0, 0, "ab", red, None);
+        frame.put_str(2, 0, "cd", None, red);
+        let output = frame.diff(Some(&previous));
+        let expected = format!(
+            "{}{}ab{}{}cd",
+            color::Fg(color::Rgb(255, 0, 0)),
+            color::Bg(color::Reset),
+            color::Fg(color::Reset),
+            color::Bg(color::Rgb(255, 0, 0))
+        );
+        assert!(output.contains(&expected), "{:?}", output);
+    }
+
+    #[test]
+    fn a_new_size_is_drawn_in_full() {
+        let mut frame = Frame::new(3, 1);
+        frame.put_str(0, 0, "abc", None, None);
+        let clear = format!("{}", termion::clear::All);
+        assert!(frame.diff(None).contains(&clear));
+        let output = frame.diff(Some(&Frame::new(4, 1)));
+        assert!(output.contains(&clear));
+        assert!(output.contains(&format!("{}{}abc", goto_str(0, 0), reset())));
+        assert!(!frame.diff(Some(&Frame::new(3, 1))).contains(&clear));
+    }
+
+    #[test]
+    fn control_characters_are_drawn_as_replacements() {
+        assert_eq!(symbols(&Cell::for_grapheme("\r", None, None)), ["\u{fffd}"]);
+        assert_eq!(symbols(&Cell::for_grapheme("\u{1b}", None, None)), ["\u{fffd}"]);
+        assert_eq!(symbols(&Cell::for_grapheme("\u{9b}", None, None)), ["\u{fffd}"]);
+        assert_eq!(symbols(&Cell::for_grapheme("a", None, None)), ["a"]);
+        assert_eq!(symbols(&Cell::for_grapheme("\u{4e16}", None, None)), ["\u{4e16}", ""]);
+
+        let mut frame = Frame::new(4, 1);
+        frame.put_str(0, 0, "a\rb", None, None);
+        let output = frame.diff(None);
+        assert!(!output.contains('\r'));
+        assert!(output.contains("a\u{fffd}b"));
+    }
+}
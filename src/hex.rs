@@ -0,0 +1,172 @@
+use crate::SearchDirection;
+
+/// The most bytes a line of the hex view shows, when there is room.
+pub const MAX_BYTES_PER_LINE: usize = 16;
+const OFFSET_WIDTH: usize = 8;
+
+// A line of the hex view is the offset of its first byte, the bytes in
+// hex with an extra space after the first half, and the same bytes as
+// text between bars:
+// `00000010  48 65 6c 6c 6f 0a 00 00  00 00 00 00 00 00 00 00  |Hello...........|`
+
+/// How many bytes each line shows in a view `width` columns wide: the
+/// most, halving until a line fits, down to one.
+pub fn bytes_per_line(width: usize) -> usize {
+    let mut per_line = MAX_BYTES_PER_LINE;
+    while per_line > 1 && bar_columns(per_line).1 >= width {
+        per_line /= 2;
+    }
+    per_line
+}
+
+/// The offset shown at the start of `line`.
+pub fn offset_text(line: usize, per_line: usize) -> String {
+    format!("{:0width$x}", line.saturating_mul(per_line), width = OFFSET_WIDTH)
+}
+
+/// The screen column of the first hex digit of byte `column` in its line.
+pub fn hex_column(column: usize, per_line: usize) -> usize {
+    let gap = if per_line > 1 && column >= per_line / 2 { 1 } else { 0 };
+    OFFSET_WIDTH + 2 + column * 3 + gap
+}
+
+/// The screen column of byte `column` in the text part of its line.
+pub fn text_column(column: usize, per_line: usize) -> usize {
+    hex_column(per_line, per_line) + 2 + column
+}
+
+/// The bar either side of the text part of a line.
+pub fn bar_columns(per_line: usize) -> (usize, usize) {
+    (text_column(0, per_line) - 1, text_column(per_line, per_line))
+}
+
+/// How `byte` is shown in the text part: printable ASCII as itself and
+/// anything else as a dot.
+pub fn printable(byte: u8) -> char {
+    if byte.is_ascii_graphic() || byte == b' ' {
+        byte as char
+    } else {
+        '.'
+    }
+}
+
+/// `byte` with its high or `low` hex digit replaced by `digit`.
+pub fn set_nibble(byte: u8, digit: u8, low: bool) -> u8 {
+    if low {
+        byte & 0xf0 | digit & 0x0f
+    } else {
+        (digit & 0x0f) << 4 | byte & 0x0f
+    }
+}
+
+/// How many lines `len` bytes take up. An empty file still has one line,
+/// for the cursor to be on.
+pub fn lines(len: usize, per_line: usize) -> usize {
+    len.div_ceil(per_line).max(1)
+}
+
+/// Parses what to search for: text in double quotes, taken as its UTF-8
+/// bytes, or pairs of hex digits, which may be separated by spaces.
+pub fn parse_pattern(pattern: &str) -> Option<Vec<u8>> {
+    let pattern = pattern.trim();
+    if let Some(text) = pattern.strip_prefix('"') {
+        let text = text.strip_suffix('"').unwrap_or(text);
+        return Some(text.as_bytes().to_vec()).filter(|bytes| !bytes.is_empty());
+    }
+    let digits: Vec<u32> = pattern
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_digit(16))
+        .collect::<Option<_>>()?;
+    if digits.is_empty() || digits.len() % 2 != 0 {
+        return None;
+    }
+    Some(digits.chunks(2).map(|pair| (pair[0] * 16 + pair[1]) as u8).collect())
+}
+
+/// Where `needle` next appears in `bytes`: the first match at or after
+/// `at` going forward, or the last one before it going backward.
+pub fn find(bytes: &[u8], needle: &[u8], at: usize, direction: SearchDirection) -> Option<usize> {
+    if needle.is_empty() || needle.len() > bytes.len() {
+        return None;
+    }
+    let last = bytes.len() - needle.len();
+    let matches = |start: &usize| bytes[*start..].starts_with(needle);
+    match direction {
+        SearchDirection::Forward => (at..=last).find(matches),
+        SearchDirection::Backward => (0..at.min(last.saturating_add(1))).rev().find(matches),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_are_hex_pairs_or_quoted_text() {
+        assert_eq!(parse_pattern("48 65 6c"), Some(vec![0x48, 0x65, 0x6c]));
+        assert_eq!(parse_pattern(" 4865 6C "), Some(vec![0x48, 0x65, 0x6c]));
+        assert_eq!(parse_pattern("\"He\""), Some(b"He".to_vec()));
+        assert_eq!(parse_pattern("\"unclosed"), Some(b"unclosed".to_vec()));
+        assert_eq!(parse_pattern("\"\u{e9}\""), Some(vec![0xc3, 0xa9]));
+        assert_eq!(parse_pattern("486"), None);
+        assert_eq!(parse_pattern("4g"), None);
+        assert_eq!(parse_pattern(""), None);
+        assert_eq!(parse_pattern("\"\""), None);
+    }
+
+    #[test]
+    fn find_stays_within_the_bytes() {
+        let bytes = b"abcabc";
+        assert_eq!(find(bytes, b"abc", 0, SearchDirection::Forward), Some(0));
+        assert_eq!(find(bytes, b"abc", 1, SearchDirection::Forward), Some(3));
+        assert_eq!(find(bytes, b"abc", 4, SearchDirection::Forward), None);
+        assert_eq!(find(bytes, b"abc", 100, SearchDirection::Forward), None);
+        assert_eq!(find(bytes, b"bc", 4, SearchDirection::Forward), Some(4));
+        // Backward, only matches starting before `at` count.
+        assert_eq!(find(bytes, b"abc", 3, SearchDirection::Backward), Some(0));
+        assert_eq!(find(bytes, b"abc", 4, SearchDirection::Backward), Some(3));
+        assert_eq!(find(bytes, b"abc", 0, SearchDirection::Backward), None);
+        assert_eq!(find(bytes, b"bc", 100, SearchDirection::Backward), Some(4));
+        assert_eq!(find(bytes, b"abcabcd", 0, SearchDirection::Forward), None);
+        assert_eq!(find(bytes, b"", 0, SearchDirection::Forward), None);
+        assert_eq!(find(b"", b"a", 0, SearchDirection::Backward), None);
+    }
+
+    #[test]
+    fn columns_leave_a_gap_halfway() {
+        assert_eq!(hex_column(0, 16), 10);
+        assert_eq!(hex_column(7, 16), 31);
+        assert_eq!(hex_column(8, 16), 35);
+        assert_eq!(text_column(0, 16), 61);
+        assert_eq!(text_column(15, 16), 76);
+        assert_eq!(bar_columns(16), (60, 77));
+        assert_eq!(hex_column(1, 1), 13);
+        assert_eq!(bar_columns(1), (14, 16));
+        assert_eq!(offset_text(2, 16), "00000020");
+        assert_eq!(offset_text(2, 4), "00000008");
+        assert_eq!(lines(0, 16), 1);
+        assert_eq!(lines(17, 16), 2);
+    }
+
+    #[test]
+    fn nibbles_are_overwritten_one_at_a_time() {
+        assert_eq!(set_nibble(0x12, 0xa, false), 0xa2);
+        assert_eq!(set_nibble(0xa2, 0xb, true), 0xab);
+        assert_eq!(set_nibble(0xff, 0, false), 0x0f);
+        assert_eq!(set_nibble(0xff, 0, true), 0xf0);
+    }
+
+    #[test]
+    fn lines_fit_the_width() {
+        assert_eq!(bytes_per_line(200), MAX_BYTES_PER_LINE);
+        assert_eq!(bytes_per_line(78), 16);
+        assert_eq!(bytes_per_line(77), 8);
+        assert_eq!(bytes_per_line(46), 8);
+        assert_eq!(bytes_per_line(45), 4);
+        assert_eq!(bytes_per_line(10), 1);
+        for width in [19, 30, 46, 78] {
+            assert!(bar_columns(bytes_per_line(width)).1 < width);
+        }
+    }
+}
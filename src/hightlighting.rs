@@ -1,18 +1,38 @@
 use termion::color;
 
-#[derive(PartialEq)]
+/// What a row ends inside of, so the next row knows how to start.
+#[derive(PartialEq, Clone, Default)]
+pub enum State {
+    #[default]
+    None,
+    BlockComment,
+    String { close: String, escapes: bool },
+}
+
+#[derive(PartialEq, Clone, Copy)]
 pub enum Type {
     None,
     Number,
     Match,
+    String,
+    Character,
+    Comment,
+    MultilineComment,
+    Keyword,
+    DataType,
 }
 
 impl Type {
-    pub fn to_color(&self) -> impl color::Color {
+    pub fn to_color(&self) -> color::Rgb {
         match self {
             Type::Number => color::Rgb(220,163,163),
             Type::Match => color::Rgb(38, 139, 210),
+            Type::String => color::Rgb(211, 54, 130),
+            Type::Character => color::Rgb(108, 113, 196),
+            Type::Comment | Type::MultilineComment => color::Rgb(133, 153, 0),
+            Type::Keyword => color::Rgb(181, 137, 0),
+            Type::DataType => color::Rgb(42, 161, 152),
             _ => color::Rgb(255,255,255),
         }
     }
-}
\ No newline at end of file
+}
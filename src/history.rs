@@ -0,0 +1,317 @@
+use crate::Position;
+
+pub enum Operation {
+    Insert { at: Position, text: String },
+    Delete { at: Position, text: String },
+    Split { at: Position },
+    Join { at: Position },
+    PushRow,
+}
+
+pub struct Step {
+    pub operations: Vec<Operation>,
+    pub cursor_before: Position,
+    pub cursor_after: Position,
+}
+
+pub struct History {
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+    sealed: bool,
+    // How many steps were on the undo stack when the document was last
+    // saved, or `None` once undoing and redoing can't get back to that.
+    saved: Option<usize>,
+    // While edits are grouped, how many steps there were before the group,
+    // so everything recorded since goes into the one step after them.
+    group: Option<usize>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            sealed: false,
+            saved: Some(0),
+            group: None,
+        }
+    }
+}
+
+impl History {
+    pub fn record(&mut self, operation: Operation, before: &Position, after: &Position) {
+        self.clear_redo();
+        if self.in_group() {
+            if let Some(step) = self.undo.last_mut() {
+                step.operations.push(operation);
+                step.cursor_after = after.clone();
+                self.sealed = false;
+                return;
+            }
+        }
+        if !self.sealed {
+            if let Some(step) = self.undo.last_mut() {
+                if continues(step, &operation) {
+                    step.operations.push(operation);
+                    step.cursor_after = after.clone();
+                    return;
+                }
+            }
+        }
+        self.sealed = false;
+        self.undo.push(Step {
+            operations: vec![operation],
+            cursor_before: before.clone(),
+            cursor_after: after.clone(),
+        });
+    }
+
+    /// Pushes several operations as a single step that later edits won't merge into.
+    pub fn record_step(&mut self, operations: Vec<Operation>, before: &Position, after: &Position) {
+        self.clear_redo();
+        self.sealed = true;
+        if self.in_group() {
+            if let Some(step) = self.undo.last_mut() {
+                step.operations.extend(operations);
+                step.cursor_after = after.clone();
+                return;
+            }
+        }
+        self.undo.push(Step {
+            operations,
+            cursor_before: before.clone(),
+            cursor_after: after.clone(),
+        });
+    }
+
+    /// Puts the edits recorded until `end_group` into one step, such as
+    /// deleting a selection and typing over it.
+    pub fn begin_group(&mut self) {
+        self.group = Some(self.undo.len());
+    }
+
+    pub fn end_group(&mut self) {
+        self.group = None;
+    }
+
+    // Whether the step on top was started by the current group.
+    fn in_group(&self) -> bool {
+        self.group.map_or(false, |start| self.undo.len() > start)
+    }
+
+    // A new edit replaces whatever could have been redone, which loses the
+    // saved state if it was among those steps.
+    fn clear_redo(&mut self) {
+        self.redo.clear();
+        if self.saved.map_or(false, |saved| saved > self.undo.len()) {
+            self.saved = None;
+        }
+    }
+
+    /// Notes that the document was saved as it is now. The current step is
+    /// closed so later edits can't merge into it.
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.undo.len());
+        self.sealed = true;
+    }
+
+    /// For a change the history doesn't record, after which no amount of
+    /// undoing or redoing gets back to the saved state.
+    pub fn forget_saved(&mut self) {
+        self.saved = None;
+    }
+
+    /// Whether undoing and redoing have got back to how it was last saved.
+    pub fn is_saved(&self) -> bool {
+        self.saved == Some(self.undo.len())
+    }
+
+    /// Closes the current step so that the next edit starts a new one.
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    pub fn pop_undo(&mut self) -> Option<Step> {
+        self.sealed = true;
+        self.undo.pop()
+    }
+
+    pub fn pop_redo(&mut self) -> Option<Step> {
+        self.sealed = true;
+        self.redo.pop()
+    }
+
+    pub fn push_undo(&mut self, step: Step) {
+        self.undo.push(step);
+    }
+
+    pub fn push_redo(&mut self, step: Step) {
+        self.redo.push(step);
+    }
+}
+
+// Typing a run of characters or pressing backspace/delete repeatedly
+// should undo in one go, so only those edits are merged into a step.
+fn continues(step: &Step, operation: &Operation) -> bool {
+    match (step.operations.last(), operation) {
+        (Some(Operation::Insert { at: last, text }), Operation::Insert { at, .. }) => {
+            at.y == last.y && at.x == last.x.saturating_add(1) && !text.starts_with(' ')
+        }
+        (Some(Operation::PushRow), Operation::Insert { at, .. }) => at.x == 0,
+        (Some(Operation::Delete { at: last, .. }), Operation::Delete { at, .. }) => {
+            at.y == last.y && (at.x == last.x || at.x.saturating_add(1) == last.x)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: usize, y: usize) -> Position {
+        Position { x, y }
+    }
+
+    fn insert(history: &mut History, x: usize, y: usize, text: &str) {
+        let operation = Operation::Insert {
+            at: at(x, y),
+            text: text.to_string(),
+        };
+        history.record(operation, &at(x, y), &at(x + 1, y));
+    }
+
+    fn delete(history: &mut History, x: usize, y: usize) {
+        let operation = Operation::Delete {
+            at: at(x, y),
+            text: "x".to_string(),
+        };
+        history.record(operation, &at(x, y), &at(x, y));
+    }
+
+    fn step_sizes(history: &mut History) -> Vec<usize> {
+        let mut sizes = Vec::new();
+        while let Some(step) = history.pop_undo() {
+            sizes.insert(0, step.operations.len());
+        }
+        sizes
+    }
+
+    #[test]
+    fn typing_merges_until_after_a_space() {
+        let mut history = History::default();
+        for (x, c) in "ab cd".chars().enumerate() {
+            insert(&mut history, x, 0, &c.to_string());
+        }
+        assert_eq!(step_sizes(&mut history), vec![3, 2]);
+    }
+
+    #[test]
+    fn typing_elsewhere_starts_a_step() {
+        let mut history = History::default();
+        insert(&mut history, 0, 0, "a");
+        insert(&mut history, 5, 0, "b");
+        insert(&mut history, 6, 1, "c");
+        assert_eq!(step_sizes(&mut history), vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn typing_on_a_pushed_row_merges() {
+        let mut history = History::default();
+        history.record(Operation::PushRow, &at(0, 1), &at(0, 1));
+        insert(&mut history, 0, 1, "a");
+        insert(&mut history, 1, 1, "b");
+        history.record(Operation::PushRow, &at(0, 2), &at(0, 2));
+        insert(&mut history, 3, 2, "c");
+        assert_eq!(step_sizes(&mut history), vec![3, 1, 1]);
+    }
+
+    #[test]
+    fn backspace_and_delete_runs_merge() {
+        let mut history = History::default();
+        // Backspace from x = 3, then delete at x = 1 twice.
+        delete(&mut history, 2, 0);
+        delete(&mut history, 1, 0);
+        delete(&mut history, 1, 0);
+        delete(&mut history, 1, 0);
+        // A run on another row is separate.
+        delete(&mut history, 1, 1);
+        insert(&mut history, 1, 1, "a");
+        delete(&mut history, 1, 1);
+        assert_eq!(step_sizes(&mut history), vec![4, 1, 1, 1]);
+    }
+
+    #[test]
+    fn recorded_steps_and_undo_close_the_step() {
+        let mut history = History::default();
+        insert(&mut history, 0, 0, "a");
+        history.record_step(vec![Operation::Split { at: at(1, 0) }], &at(1, 0), &at(0, 1));
+        insert(&mut history, 0, 1, "b");
+        insert(&mut history, 1, 1, "c");
+        let step = history.pop_undo().unwrap();
+        history.push_redo(step);
+        let step = history.pop_redo().unwrap();
+        history.push_undo(step);
+        insert(&mut history, 2, 1, "d");
+        assert_eq!(step_sizes(&mut history), vec![1, 1, 2, 1]);
+    }
+
+    #[test]
+    fn grouped_edits_are_one_step() {
+        let mut history = History::default();
+        insert(&mut history, 0, 0, "a");
+        history.begin_group();
+        history.record_step(vec![Operation::Join { at: at(1, 0) }], &at(1, 0), &at(1, 0));
+        insert(&mut history, 1, 0, "b");
+        history.end_group();
+        // Typing on merges as usual.
+        insert(&mut history, 2, 0, "c");
+        // A group with nothing deleted doesn't join the step before it.
+        history.begin_group();
+        insert(&mut history, 7, 0, "d");
+        history.end_group();
+        assert_eq!(step_sizes(&mut history), vec![1, 3, 1]);
+    }
+
+    #[test]
+    fn saved_state_is_found_by_undo_and_redo() {
+        let mut history = History::default();
+        assert!(history.is_saved());
+        insert(&mut history, 0, 0, "a");
+        assert!(!history.is_saved());
+        history.mark_saved();
+        assert!(history.is_saved());
+        // The next edit can't merge into the saved step.
+        insert(&mut history, 1, 0, "b");
+        assert!(!history.is_saved());
+        let step = history.pop_undo().unwrap();
+        assert_eq!(step.operations.len(), 1);
+        history.push_redo(step);
+        assert!(history.is_saved());
+        let step = history.pop_undo().unwrap();
+        history.push_redo(step);
+        assert!(!history.is_saved());
+        let step = history.pop_redo().unwrap();
+        history.push_undo(step);
+        assert!(history.is_saved());
+    }
+
+    #[test]
+    fn saved_state_is_lost_with_the_redo_steps() {
+        let mut history = History::default();
+        insert(&mut history, 0, 0, "a");
+        history.mark_saved();
+        let step = history.pop_undo().unwrap();
+        history.push_redo(step);
+        insert(&mut history, 0, 0, "b");
+        let step = history.pop_undo().unwrap();
+        history.push_redo(step);
+        assert!(!history.is_saved());
+
+        let mut history = History::default();
+        history.forget_saved();
+        insert(&mut history, 0, 0, "a");
+        history.pop_undo();
+        assert!(!history.is_saved());
+    }
+}
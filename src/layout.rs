@@ -0,0 +1,278 @@
+use std::cmp;
+use std::mem;
+
+const MIN_PERCENT: u16 = 10;
+const MAX_PERCENT: u16 = 90;
+
+/// Which way a split divides its area: a horizontal split stacks its panes
+/// one above the other, a vertical split puts them side by side.
+#[derive(PartialEq, Copy, Clone)]
+pub enum Orientation {
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Clone, Copy)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// How the screen is divided between panes, as a tree of splits whose
+/// leaves are indices of panes.
+pub enum Layout {
+    Pane(usize),
+    Split {
+        orientation: Orientation,
+        // How much of the area goes to `first`.
+        percent: u16,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+impl Layout {
+    /// Splits `pane` in two, with `new_pane` taking the second half.
+    pub fn split(&mut self, pane: usize, new_pane: usize, orientation: Orientation) -> bool {
+        match self {
+            Layout::Pane(index) if *index == pane => {
+                *self = Layout::Split {
+                    orientation,
+                    percent: 50,
+                    first: Box::new(Layout::Pane(pane)),
+                    second: Box::new(Layout::Pane(new_pane)),
+                };
+                true
+            }
+            Layout::Pane(_) => false,
+            Layout::Split { first, second, .. } => {
+                first.split(pane, new_pane, orientation) || second.split(pane, new_pane, orientation)
+            }
+        }
+    }
+
+    /// Takes `pane` out, giving its space to its sibling, and renumbers the
+    /// panes after it to match their indices once it is removed.
+    pub fn remove(&mut self, pane: usize) -> bool {
+        let removed = self.remove_leaf(pane);
+        if removed {
+            self.renumber(pane);
+        }
+        removed
+    }
+
+    fn remove_leaf(&mut self, pane: usize) -> bool {
+        let (first, second) = match self {
+            Layout::Pane(_) => return false,
+            Layout::Split { first, second, .. } => (first, second),
+        };
+        let sibling = match (first.as_ref(), second.as_ref()) {
+            (Layout::Pane(index), _) if *index == pane => mem::replace(second.as_mut(), Layout::Pane(0)),
+            (_, Layout::Pane(index)) if *index == pane => mem::replace(first.as_mut(), Layout::Pane(0)),
+            _ => return first.remove_leaf(pane) || second.remove_leaf(pane),
+        };
+        *self = sibling;
+        true
+    }
+
+    fn renumber(&mut self, removed: usize) {
+        match self {
+            Layout::Pane(index) => {
+                if *index > removed {
+                    *index -= 1;
+                }
+            }
+            Layout::Split { first, second, .. } => {
+                first.renumber(removed);
+                second.renumber(removed);
+            }
+        }
+    }
+
+    /// Grows `pane` by `delta` percent of the split it is directly in, or
+    /// shrinks it if `delta` is negative.
+    pub fn resize(&mut self, pane: usize, delta: i16) -> bool {
+        let (percent, first, second) = match self {
+            Layout::Pane(_) => return false,
+            Layout::Split {
+                percent,
+                first,
+                second,
+                ..
+            } => (percent, first, second),
+        };
+        let delta = if matches!(first.as_ref(), Layout::Pane(index) if *index == pane) {
+            delta
+        } else if matches!(second.as_ref(), Layout::Pane(index) if *index == pane) {
+            -delta
+        } else {
+            return first.resize(pane, delta) || second.resize(pane, delta);
+        };
+        let resized = (*percent as i16).saturating_add(delta);
+        *percent = (resized.max(0) as u16).clamp(MIN_PERCENT, MAX_PERCENT);
+        true
+    }
+
+    /// The panes in screen order, left to right and top to bottom.
+    pub fn panes(&self) -> Vec<usize> {
+        match self {
+            Layout::Pane(index) => vec![*index],
+            Layout::Split { first, second, .. } => {
+                let mut panes = first.panes();
+                panes.extend(second.panes());
+                panes
+            }
+        }
+    }
+
+    /// Places every pane within `area`, returning where each one goes and
+    /// the one column wide dividers between side by side panes.
+    pub fn arrange(&self, area: Rect) -> (Vec<(usize, Rect)>, Vec<Rect>) {
+        let mut panes = Vec::new();
+        let mut dividers = Vec::new();
+        self.arrange_into(area, &mut panes, &mut dividers);
+        (panes, dividers)
+    }
+
+    fn arrange_into(&self, area: Rect, panes: &mut Vec<(usize, Rect)>, dividers: &mut Vec<Rect>) {
+        let (orientation, percent, first, second) = match self {
+            Layout::Pane(index) => {
+                panes.push((*index, area));
+                return;
+            }
+            Layout::Split {
+                orientation,
+                percent,
+                first,
+                second,
+            } => (*orientation, *percent as usize, first, second),
+        };
+        match orientation {
+            Orientation::Horizontal => {
+                let first_height = share(area.height, percent);
+                first.arrange_into(Rect { height: first_height, ..area }, panes, dividers);
+                second.arrange_into(
+                    Rect {
+                        y: area.y + first_height,
+                        height: area.height - first_height,
+                        ..area
+                    },
+                    panes,
+                    dividers,
+                );
+            }
+            Orientation::Vertical => {
+                let available = area.width.saturating_sub(1);
+                let first_width = share(available, percent);
+                first.arrange_into(Rect { width: first_width, ..area }, panes, dividers);
+                dividers.push(Rect {
+                    x: area.x + first_width,
+                    width: cmp::min(area.width, 1),
+                    ..area
+                });
+                second.arrange_into(
+                    Rect {
+                        x: area.x + first_width + 1,
+                        width: available - first_width,
+                        ..area
+                    },
+                    panes,
+                    dividers,
+                );
+            }
+        }
+    }
+}
+
+// `percent` of `total`, leaving at least one line or column on each side
+// where there is room for it.
+fn share(total: usize, percent: usize) -> usize {
+    if total < 2 {
+        return total;
+    }
+    (total * percent / 100).clamp(1, total - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(width: usize, height: usize) -> Rect {
+        Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }
+    }
+
+    // Each pane's rect as (x, y, width, height), in pane order.
+    fn rects(layout: &Layout, width: usize, height: usize) -> Vec<(usize, usize, usize, usize)> {
+        let (mut panes, _) = layout.arrange(area(width, height));
+        panes.sort_by_key(|(pane, _)| *pane);
+        panes
+            .into_iter()
+            .map(|(_, rect)| (rect.x, rect.y, rect.width, rect.height))
+            .collect()
+    }
+
+    #[test]
+    fn splits_share_the_area() {
+        let mut layout = Layout::Pane(0);
+        assert!(layout.split(0, 1, Orientation::Horizontal));
+        assert_eq!(rects(&layout, 80, 24), [(0, 0, 80, 12), (0, 12, 80, 12)]);
+        assert!(layout.split(1, 2, Orientation::Vertical));
+        assert_eq!(
+            rects(&layout, 80, 24),
+            [(0, 0, 80, 12), (0, 12, 39, 12), (40, 12, 40, 12)]
+        );
+        let (_, dividers) = layout.arrange(area(80, 24));
+        assert_eq!(dividers.len(), 1);
+        assert_eq!((dividers[0].x, dividers[0].y, dividers[0].width, dividers[0].height), (39, 12, 1, 12));
+        assert_eq!(layout.panes(), [0, 1, 2]);
+        assert!(!layout.split(5, 6, Orientation::Vertical));
+    }
+
+    #[test]
+    fn removing_a_pane_renumbers_the_rest() {
+        let mut layout = Layout::Pane(0);
+        layout.split(0, 1, Orientation::Vertical);
+        layout.split(0, 2, Orientation::Horizontal);
+        assert_eq!(layout.panes(), [0, 2, 1]);
+        assert!(layout.remove(0));
+        assert_eq!(layout.panes(), [1, 0]);
+        assert_eq!(rects(&layout, 81, 10), [(41, 0, 40, 10), (0, 0, 40, 10)]);
+        assert!(!layout.remove(7));
+        assert!(layout.remove(1));
+        assert_eq!(layout.panes(), [0]);
+        assert!(!layout.remove(0));
+    }
+
+    #[test]
+    fn resizing_stays_within_limits() {
+        let mut layout = Layout::Pane(0);
+        assert!(!layout.resize(0, 5));
+        layout.split(0, 1, Orientation::Horizontal);
+        assert!(layout.resize(0, 20));
+        assert_eq!(rects(&layout, 10, 20), [(0, 0, 10, 14), (0, 14, 10, 6)]);
+        assert!(layout.resize(1, 20));
+        assert_eq!(rects(&layout, 10, 20), [(0, 0, 10, 10), (0, 10, 10, 10)]);
+        layout.resize(0, 100);
+        assert_eq!(rects(&layout, 10, 20), [(0, 0, 10, 18), (0, 18, 10, 2)]);
+        layout.resize(0, -100);
+        assert_eq!(rects(&layout, 10, 20), [(0, 0, 10, 2), (0, 2, 10, 18)]);
+    }
+
+    #[test]
+    fn tiny_areas_keep_a_line_each_where_they_can() {
+        let mut layout = Layout::Pane(0);
+        layout.split(0, 1, Orientation::Horizontal);
+        layout.resize(0, -40);
+        assert_eq!(rects(&layout, 5, 3), [(0, 0, 5, 1), (0, 1, 5, 2)]);
+        assert_eq!(rects(&layout, 5, 1), [(0, 0, 5, 1), (0, 1, 5, 0)]);
+        assert_eq!(share(0, 50), 0);
+        assert_eq!(share(2, 90), 1);
+    }
+}
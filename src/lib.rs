@@ -1,3 +1,41 @@
+#![allow(clippy::all, clippy::pedantic, clippy::restriction)]
+#![allow(
+    clippy::missing_docs_in_private_items,
+    clippy::implicit_return,
+    clippy::shadow_reuse,
+    clippy::print_stdout,
+    clippy::wildcard_enum_match_arm,
+    clippy::else_if_without_else
+)]
+
+mod clipboard;
+mod document;
+mod editor;
+mod encoding;
+mod filetype;
+mod frame;
+mod hex;
+mod history;
+mod layout;
+mod row;
+mod swap;
+mod terminal;
+// Stands in for the terminal when the editor is driven by tests.
+#[cfg(test)]
+mod virtual_screen;
+mod hightlighting;
+
+pub use document::Document;
+pub use editor::Editor;
+pub use editor::Position;
+pub use editor::SearchDirection;
+pub use row::Row;
+pub use terminal::Terminal;
+
+// The storage was written against the default lints and stays that way.
+#[warn(clippy::all)]
+pub mod text;
+
 // mod row;
 
 // #[cfg(test)]
@@ -8,18 +8,7 @@
     clippy::else_if_without_else
 )]
 
-mod document;
-mod editor;
-mod row;
-mod terminal;
-mod hightlighting;
-
-pub use document::Document;
-use editor::Editor;
-pub use editor::Position;
-pub use editor::SearchDirection;
-pub use row::Row;
-pub use terminal::Terminal;
+use hecto::Editor;
 
 fn main() {
     // let mut editor = Editor::default();
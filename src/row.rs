@@ -1,22 +1,35 @@
 use crate::SearchDirection;
+use regex::Regex;
 use std::cmp;
+use std::ops::Range;
 use termion::color;
+use crate::filetype::HighlightingOptions;
+use crate::frame::Cell;
 use crate::hightlighting;
+use crate::text::Text;
 use unicode_segmentation::UnicodeSegmentation;
 
+const SELECTION_BG_COLOR: color::Rgb = color::Rgb(68, 68, 120);
+// How many graphemes of a row are highlighted on each edit. Anything past
+// that waits until the rows below need to know how the row ends.
+const MAX_HIGHTLIGHT_LEN: usize = 10_000;
+
 #[derive(Default)]
 pub struct Row {
-    pub string: String,
+    text: Text,
     hightlighting: Vec<hightlighting::Type>,
-    len: usize,
+    start_state: hightlighting::State,
+    // `None` until the whole row has been highlighted.
+    end_state: Option<hightlighting::State>,
 }
 
 impl From<&str> for Row {
     fn from(slice: &str) -> Self {
-        let mut row = Self {
-            string: String::from(slice),
+        let row = Self {
+            text: Text::from(slice),
             hightlighting: Vec::new(),
-            len: slice.graphemes(true).count(),
+            start_state: hightlighting::State::None,
+            end_state: None,
         };
         // row.update_len();
         row
@@ -24,227 +37,288 @@ impl From<&str> for Row {
 }
 
 impl Row {
-    pub fn render(&self, start: usize, end: usize) -> String {
-        let end = cmp::min(end, self.string.len());
-        let start = cmp::min(start, end);
-        // self.string.get(start..end).unwrap_or_default().to_string()
-        let mut result = String::new();
-        
-        let mut current_hightlighting = &hightlighting::Type::None;
-
-        for (index, grapheme) in self.string[..]
-            .graphemes(true)
-            .enumerate()
-            .skip(start)
-            .take(end - start)
-        {
-            // result.push_str(grapheme)
-            // // if grapheme == "\t" {
-            // //     result.push(' ');
-            // // } else {
-            // //     result.push_str(grapheme);
-            // // }
-            if let Some(c) = grapheme.chars().next() {
-                
-                let hightlighting_type = self.hightlighting.get(index).unwrap_or(&hightlighting::Type::None);
-                if hightlighting_type != current_hightlighting {
-                    current_hightlighting = hightlighting_type;
-                    let start_highlight = format!("{}", termion::color::Fg(hightlighting_type.to_color()));
-                    result.push_str(&start_highlight[..]);
+    /// Renders screen columns `start..end` of the row as cells, giving the
+    /// graphemes in `selection` a distinct background. Tabs are expanded to
+    /// the next multiple of `tab_width`, and a wide grapheme cut by either
+    /// edge shows as spaces.
+    pub fn render(
+        &self,
+        start: usize,
+        end: usize,
+        selection: Option<Range<usize>>,
+        tab_width: usize,
+    ) -> Vec<Cell> {
+        let mut result = Vec::new();
 
-                }
+        for (index, grapheme, column, width) in self.text.columns_from_column(start, tab_width) {
+            if column >= end {
+                break;
+            }
+            let next_column = column.saturating_add(width);
+            if next_column <= start {
+                continue;
+            }
 
-                if c == '\t' {
-                    result.push_str(" ")
-                // } else if c.is_ascii_digit() {
-                //     result.push_str(
-                //         &format!(
-                //             "{}{}{}",
-                //             termion::color::Fg(color::Rgb(220,163,163)),
-                //             c,
-                //             color::Fg(color::Reset)
-                //         )[..],
-                //     );
-                } else {
-                    result.push(c);
-                }
+            let hightlighting_type = self.hightlighting.get(index).unwrap_or(&hightlighting::Type::None);
+            let fg = match hightlighting_type {
+                hightlighting::Type::None => None,
+                _ => Some(hightlighting_type.to_color()),
+            };
+            let selected = selection.as_ref().map_or(false, |range| range.contains(&index));
+            let bg = if selected { Some(SELECTION_BG_COLOR) } else { None };
+
+            if column < start || next_column > end || grapheme == "\t" {
+                let visible = cmp::min(next_column, end).saturating_sub(cmp::max(column, start));
+                result.resize(result.len().saturating_add(visible), Cell::new(" ", fg, bg));
+            } else {
+                result.append(&mut Cell::for_grapheme(grapheme, fg, bg));
             }
         }
-        let end_highlight = format!("{}", termion::color::Fg(color::Reset));
-        result.push_str((&end_highlight[..]));
         result
     }
 
+    /// The screen column grapheme `index` starts at.
+    pub fn width_to(&self, index: usize, tab_width: usize) -> usize {
+        let mut columns = self.text.columns_from_index(index, tab_width);
+        match columns.find(|(at, ..)| *at == index) {
+            Some((_, _, column, _)) => column,
+            None => columns.column(),
+        }
+    }
+
+    /// How many screen columns grapheme `index` takes up, or 1 past the end
+    /// of the row.
+    pub fn width_at(&self, index: usize, tab_width: usize) -> usize {
+        self.text
+            .columns_from_index(index, tab_width)
+            .find(|(at, ..)| *at == index)
+            .map_or(1, |(_, _, _, width)| width)
+    }
+
+    /// The index of the grapheme covering screen column `column`, or the
+    /// row's length if it is past the end.
+    pub fn index_at(&self, column: usize, tab_width: usize) -> usize {
+        self.text
+            .columns_from_column(column, tab_width)
+            .find(|(_, _, start, width)| start.saturating_add(*width) > column)
+            .map_or(self.len(), |(index, ..)| index)
+    }
+
+    /// Splits the row into screen lines of at most `width` columns for soft
+    /// wrapping, returning the index of the grapheme each line starts at.
+    /// With `at_words`, lines break after the last whitespace that fits when
+    /// there is one. A row that exactly fills its last line gets an empty
+    /// line after it, so the cursor has somewhere to go at the end.
+    pub fn wrap(&self, width: usize, tab_width: usize, at_words: bool) -> Vec<usize> {
+        let width = cmp::max(width, 1);
+        let graphemes: Vec<(bool, usize, usize)> = self
+            .text
+            .columns(tab_width)
+            .map(|(_, grapheme, column, width)| (grapheme.trim().is_empty(), column, width))
+            .collect();
+        let mut starts = vec![0];
+        let mut line_column: usize = 0;
+        let mut last_break = None;
+        let mut index = 0;
+        while index < graphemes.len() {
+            let (whitespace, column, grapheme_width) = graphemes[index];
+            let line_start = starts[starts.len() - 1];
+            if column.saturating_add(grapheme_width) > line_column.saturating_add(width)
+                && index > line_start
+            {
+                let start = match last_break {
+                    Some(at) if at_words && at > line_start => at,
+                    _ => index,
+                };
+                starts.push(start);
+                line_column = graphemes[start].1;
+                last_break = None;
+                index = start;
+                continue;
+            }
+            if whitespace {
+                last_break = Some(index.saturating_add(1));
+            }
+            index += 1;
+        }
+        let end_column = graphemes
+            .last()
+            .map_or(0, |(_, column, width)| column.saturating_add(*width));
+        if !self.is_empty() && end_column >= line_column.saturating_add(width) {
+            starts.push(self.len());
+        }
+        starts
+    }
+
     pub fn len(&self) -> usize {
         // self.string.len()
-        self.len
+        self.text.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.string.is_empty()
+        self.text.is_empty()
     }
 
     pub fn insert(&mut self, at: usize, c: char) {
-        if at >= self.len() {
-            self.string.push(c);
-            self.len += 1;
-            return;
-        }
-        // } else {
-        let mut result = String::new();
-        let mut length = 0;
-        for (idx, grapheme) in self.string[..].graphemes(true).enumerate() {
-            length += 1;
-            if idx == at {
-                length += 1;
-                result.push(c);
-            }
-            result.push_str(grapheme);
-        }
-        self.len = length;
-        self.string = result;
+        self.text.insert(at, c.encode_utf8(&mut [0; 4]));
     }
 
-    pub fn delete(&mut self, at: usize) {
-        if at > self.len() {
-            return;
-        }
-        let mut result = String::new();
-        let mut length = 0;
-        for (idx, grapheme) in self.string[..].graphemes(true).enumerate() {
-            if idx != at {
-                length += 1;
-                result.push_str(grapheme);
-            }
-        }
-        self.len = length;
-        self.string = result;
+    pub fn insert_str(&mut self, at: usize, text: &str) {
+        self.text.insert(at, text);
     }
 
-    pub fn append(&mut self, new: &Self) {
-        self.string = format!("{}{}", self.string, new.string);
-        self.len += new.len;
+    pub fn grapheme(&self, at: usize) -> Option<&str> {
+        self.text.grapheme(at)
     }
 
-    pub fn split(&mut self, at: usize) -> Self {
-        let mut result_s = String::new();
-        let mut length_s = 0;
-        let mut split_row = String::new();
-        let mut length_row = 0;
-        for (idx, grapheme) in self.string[..].graphemes(true).enumerate() {
-            if idx < at {
-                length_s += 1;
-                result_s.push_str(grapheme);
-            } else {
-                length_row += 1;
-                split_row.push_str(grapheme);
-            }
-        }
-        self.len = length_s;
-        self.string = result_s;
+    /// Deletes the graphemes from `start` up to (not including) `end`.
+    pub fn delete(&mut self, start: usize, end: usize) {
+        self.text.remove(start, end);
+    }
+
+    // Both keep the end state the row below was last highlighted from, so
+    // re-highlighting knows whether to carry on to it.
+    pub fn append(&mut self, new: Self) {
+        self.text.append(new.text);
+        self.end_state = new.end_state;
+    }
 
+    pub fn split(&mut self, at: usize) -> Self {
         Self {
-            string: split_row,
+            text: self.text.split_off(at),
             hightlighting: Vec::new(),
-            len: length_row,
+            start_state: hightlighting::State::None,
+            end_state: self.end_state.clone(),
         }
     }
 
-    pub fn as_bytes(&self) -> &[u8] {
-        self.string.as_bytes()
-    }
-
-    pub fn hightlight(&mut self, word: Option<&str>) {
-        // let mut hightlighting = Vec::new();
-        
-        // for c in self.string.chars() {
-        //     if c.is_ascii_digit() {
-        //         hightlighting.push(hightlighting::Type::Number);
-        //     } else {
-        //         hightlighting.push(hightlighting::Type::None);
-        //     }
-        // }
-        // self.hightlighting = hightlighting;
-
-        let mut highlighting = Vec::new();            
-        let chars: Vec<char> = self.string.chars().collect();            
-        let mut matches = Vec::new();            
-        let mut search_index = 0;            
-
-        if let Some(word) = word {
-            while let Some(search_match) = self.find(word, search_index, SearchDirection::Forward) {            
-                matches.push(search_match);
-                if let Some(next_index) = search_match.checked_add(word[..].graphemes(true).count())            
-                {
-                    search_index = next_index;
-                } else {
-                    break;
-                }
-            }
+    /// The row's text in the pieces it is stored in.
+    pub fn chunks(&self) -> impl Iterator<Item = &str> {
+        self.text.chunks()
+    }
+
+    /// Highlights the row as if it starts inside `start`. Only the first
+    /// `MAX_HIGHTLIGHT_LEN` graphemes of a longer row are done, and what it
+    /// ends inside of stays unknown until `finish_hightlight`.
+    pub fn hightlight(
+        &mut self,
+        opts: &HighlightingOptions,
+        word: Option<&str>,
+        start: &hightlighting::State,
+    ) {
+        self.start_state = start.clone();
+        self.hightlight_up_to(opts, word, MAX_HIGHTLIGHT_LEN);
+    }
+
+    /// Highlights the rest of a row `hightlight` stopped short on and
+    /// returns what the row ends inside of.
+    pub fn finish_hightlight(
+        &mut self,
+        opts: &HighlightingOptions,
+        word: Option<&str>,
+    ) -> &hightlighting::State {
+        if self.end_state.is_none() {
+            self.hightlight_up_to(opts, word, usize::MAX);
         }
+        self.end_state.get_or_insert_with(hightlighting::State::default)
+    }
 
+    // One grapheme past `limit` is looked at so a token ending right on it
+    // is still recognised.
+    fn hightlight_up_to(&mut self, opts: &HighlightingOptions, word: Option<&str>, limit: usize) {
+        let graphemes: Vec<&str> = self.text.graphemes().take(limit.saturating_add(1)).collect();
+        let cut = graphemes.len() > limit;
+        let start = &self.start_state;
+        let mut hightlighting = Vec::new();
         let mut index = 0;
-        while let Some(c) = chars.get(index) {
-            if let Some(word) = word {
-                if matches.contains(&index) {
-                    for _ in word[..].graphemes(true) {
-                        index += 1;
-                        highlighting.push(hightlighting::Type::Match);
+        let mut state = start.clone();
+
+        match start {
+            hightlighting::State::BlockComment => {
+                if let Some((_, close)) = opts.block_comment() {
+                    if continue_block_comment(&mut index, close, &graphemes, &mut hightlighting) {
+                        state = hightlighting::State::None;
+                    }
+                }
+            }
+            hightlighting::State::String { close, escapes } => {
+                if continue_string(&mut index, close, *escapes, &graphemes, &mut hightlighting) {
+                    state = hightlighting::State::None;
+                }
+            }
+            hightlighting::State::None => {
+                if let Some(marker) = opts.headings() {
+                    if graphemes.first().map_or(false, |first| first.starts_with(marker)) {
+                        hightlighting = vec![hightlighting::Type::Keyword; graphemes.len()];
+                        index = graphemes.len();
                     }
-                    continue;
                 }
             }
+        }
 
-            if c.is_ascii_digit() {
-                highlighting.push(hightlighting::Type::Number);
-            } else {
-                highlighting.push(hightlighting::Type::None);
+        while index < graphemes.len() {
+            if hightlight_block_comment(&mut index, opts, &graphemes, &mut hightlighting, &mut state)
+                || hightlight_line_comment(&mut index, opts, &graphemes, &mut hightlighting)
+                || hightlight_string(&mut index, opts, &graphemes, &mut hightlighting, &mut state)
+                || hightlight_char(&mut index, opts, &graphemes, &mut hightlighting)
+                || hightlight_number(&mut index, opts, &graphemes, &mut hightlighting)
+                || hightlight_keywords(
+                    &mut index,
+                    opts.keywords(),
+                    hightlighting::Type::Keyword,
+                    &graphemes,
+                    &mut hightlighting,
+                )
+                || hightlight_keywords(
+                    &mut index,
+                    opts.data_types(),
+                    hightlighting::Type::DataType,
+                    &graphemes,
+                    &mut hightlighting,
+                )
+            {
+                continue;
             }
+            hightlighting.push(hightlighting::Type::None);
             index += 1;
         }
 
-        self.hightlighting = highlighting;
-
-        // let mut hightlighting = Vec::new();
-
-        // let chars: Vec<char> = self.string.chars().collect();
-        // let mut matchs = Vec::new();
-        // let mut search_index = 0;
-
-        // if let Some(word) = word {
-        //     while let Some(search_match) = self.find(word, search_index, SearchDirection::Forward) {
-        //         matchs.push(search_match);
-        //         if let Some(next_index) = search_match.checked_add(word[..].graphemes(true).count()) {
-        //             search_index = next_index;
-        //         } else {
-        //             break;
-        //         }
-        //     }
-        // }
-
-        // let mut index = 0;
-        // while let Some(c) = chars.get(index) {
-        //     if let Some(word) = word {
-        //         if matchs.contains(&index) {
-        //             for _ in word[..].graphemes(true) {
-        //                 index += 1;
-        //                 hightlighting.push(hightlighting::Type::Match);
-        //             }
-        //             continue;
-        //         }
-        //     }
-        //     if c.is_ascii_digit() {
-        //         hightlighting.push(hightlighting::Type::Number);
-        //     } else {
-        //         hightlighting.push(hightlighting::Type::None);
-        //     }
-        // }
-        // self.hightlighting = hightlighting;
+        if cut {
+            hightlighting.truncate(limit);
+            self.end_state = None;
+        } else {
+            self.end_state = Some(state);
+        }
+        self.hightlighting = hightlighting;
+        self.hightlight_match(word);
+    }
 
+    /// What the row ends inside of, if it has been highlighted to the end.
+    pub fn end_state(&self) -> Option<&hightlighting::State> {
+        self.end_state.as_ref()
+    }
+
+    // Only looks through the part of the row that has been highlighted.
+    fn hightlight_match(&mut self, word: Option<&str>) {
+        if let Some(word) = word.filter(|word| !word.is_empty()) {
+            let len = word[..].graphemes(true).count();
+            let highlighted = self.text.slice(0, self.hightlighting.len());
+            for (byte_index, _) in highlighted.match_indices(word) {
+                let search_match = self.text.grapheme_index(byte_index);
+                if self.text.byte_index(search_match) != Some(byte_index) {
+                    continue;
+                }
+                for index in search_match..search_match.saturating_add(len) {
+                    if let Some(hightlighting) = self.hightlighting.get_mut(index) {
+                        *hightlighting = hightlighting::Type::Match;
+                    }
+                }
+            }
+        }
     }
 
     pub fn find(&self, query: &str, at: usize, direction: SearchDirection) -> Option<usize> {
-        if at > self.len || query.is_empty() {
+        if at > self.len() || query.is_empty() {
             return None;
         }
         let start = if direction == SearchDirection::Forward {
@@ -253,15 +327,11 @@ impl Row {
             0
         };
         let end = if direction == SearchDirection::Forward {
-            self.len
+            self.len()
         } else {
             at
         };
-        let sub_string: String = self.string[..]
-            .graphemes(true)
-            .skip(start)
-            .take(end - start)
-            .collect();
+        let sub_string = self.text.slice(start, end);
         let matching_byte_index = if direction == SearchDirection::Forward {
             sub_string.find(query)
         } else {
@@ -277,6 +347,29 @@ impl Row {
         }
         None
     }
+    pub fn find_regex(&self, regex: &Regex, at: usize) -> Option<(usize, usize)> {
+        let byte_index = self.text.byte_index(at)?;
+        let string = self.text.as_str();
+        let found = regex.find_at(&string, byte_index)?;
+        Some((
+            self.text.grapheme_index(found.start()),
+            self.text.grapheme_index(found.end()),
+        ))
+    }
+
+    pub fn expand_regex(&self, regex: &Regex, at: usize, replacement: &str) -> Option<String> {
+        let byte_index = self.text.byte_index(at)?;
+        let string = self.text.as_str();
+        let captures = regex.captures_at(&string, byte_index)?;
+        let mut result = String::new();
+        captures.expand(replacement, &mut result);
+        Some(result)
+    }
+
+    pub fn substring(&self, start: usize, end: usize) -> String {
+        self.text.slice(start, end)
+    }
+
     // pub fn find(&self, query: &str, after: usize, direction: usize) -> Option<usize> {
     //     let sub_string: String = self.string[..].graphemes(true).skip(after).collect();
     //     let matching_byte_index = sub_string.find(query);
@@ -292,4 +385,291 @@ impl Row {
 
 }
 
+fn is_separator(grapheme: &str) -> bool {
+    grapheme.chars().next().map_or(true, |c| {
+        c.is_ascii_whitespace() || (c.is_ascii_punctuation() && c != '_')
+    })
+}
+
+fn starts_with_at(graphemes: &[&str], index: usize, pattern: &str) -> bool {
+    let mut rest = pattern;
+    for grapheme in graphemes.iter().skip(index) {
+        if rest.is_empty() {
+            break;
+        }
+        match rest.strip_prefix(grapheme) {
+            Some(remaining) => rest = remaining,
+            None => return false,
+        }
+    }
+    rest.is_empty()
+}
+
+fn hightlight_line_comment(
+    index: &mut usize,
+    opts: &HighlightingOptions,
+    graphemes: &[&str],
+    hightlighting: &mut Vec<hightlighting::Type>,
+) -> bool {
+    if let Some(marker) = opts.line_comment() {
+        if starts_with_at(graphemes, *index, marker) {
+            for _ in *index..graphemes.len() {
+                hightlighting.push(hightlighting::Type::Comment);
+            }
+            *index = graphemes.len();
+            return true;
+        }
+    }
+    false
+}
+
+fn hightlight_block_comment(
+    index: &mut usize,
+    opts: &HighlightingOptions,
+    graphemes: &[&str],
+    hightlighting: &mut Vec<hightlighting::Type>,
+    state: &mut hightlighting::State,
+) -> bool {
+    if let Some((open, close)) = opts.block_comment() {
+        if starts_with_at(graphemes, *index, open) {
+            for _ in open[..].graphemes(true) {
+                hightlighting.push(hightlighting::Type::MultilineComment);
+            }
+            *index += open[..].graphemes(true).count();
+            if !continue_block_comment(index, close, graphemes, hightlighting) {
+                *state = hightlighting::State::BlockComment;
+            }
+            return true;
+        }
+    }
+    false
+}
+
+// Returns whether the comment was closed on this row.
+fn continue_block_comment(
+    index: &mut usize,
+    close: &str,
+    graphemes: &[&str],
+    hightlighting: &mut Vec<hightlighting::Type>,
+) -> bool {
+    let close_len = close[..].graphemes(true).count();
+    while *index < graphemes.len() {
+        if starts_with_at(graphemes, *index, close) {
+            for _ in 0..close_len {
+                hightlighting.push(hightlighting::Type::MultilineComment);
+            }
+            *index += close_len;
+            return true;
+        }
+        hightlighting.push(hightlighting::Type::MultilineComment);
+        *index += 1;
+    }
+    false
+}
+
+fn hightlight_string(
+    index: &mut usize,
+    opts: &HighlightingOptions,
+    graphemes: &[&str],
+    hightlighting: &mut Vec<hightlighting::Type>,
+    state: &mut hightlighting::State,
+) -> bool {
+    let (open_len, close, escapes, multiline) = if let Some((open_len, close)) =
+        raw_string_at(*index, opts, graphemes)
+    {
+        (open_len, close, false, true)
+    } else {
+        match graphemes[*index].chars().next() {
+            Some(c) if opts.strings().contains(c) => {
+                let triple = c.to_string().repeat(3);
+                if opts.triple_quotes() && starts_with_at(graphemes, *index, &triple) {
+                    (3, triple, true, true)
+                } else {
+                    (1, c.to_string(), true, opts.multiline_strings())
+                }
+            }
+            _ => return false,
+        }
+    };
+    for _ in 0..open_len {
+        hightlighting.push(hightlighting::Type::String);
+    }
+    *index += open_len;
+    if !continue_string(index, &close, escapes, graphemes, hightlighting) && multiline {
+        *state = hightlighting::State::String { close, escapes };
+    }
+    true
+}
+
+// Recognises Rust's `r"..."` and `r#"..."#`, returning the opening length
+// and the closing delimiter.
+fn raw_string_at(
+    index: usize,
+    opts: &HighlightingOptions,
+    graphemes: &[&str],
+) -> Option<(usize, String)> {
+    if !opts.raw_strings() || graphemes[index] != "r" {
+        return None;
+    }
+    if index > 0 && !is_separator(graphemes[index - 1]) {
+        return None;
+    }
+    let hashes = graphemes
+        .iter()
+        .skip(index + 1)
+        .take_while(|grapheme| **grapheme == "#")
+        .count();
+    if graphemes.get(index + 1 + hashes) != Some(&"\"") {
+        return None;
+    }
+    Some((hashes + 2, format!("\"{}", "#".repeat(hashes))))
+}
+
+// Returns whether the string was closed on this row.
+fn continue_string(
+    index: &mut usize,
+    close: &str,
+    escapes: bool,
+    graphemes: &[&str],
+    hightlighting: &mut Vec<hightlighting::Type>,
+) -> bool {
+    let close_len = close[..].graphemes(true).count();
+    while *index < graphemes.len() {
+        if escapes && graphemes[*index] == "\\" {
+            hightlighting.push(hightlighting::Type::String);
+            *index += 1;
+            if *index < graphemes.len() {
+                hightlighting.push(hightlighting::Type::String);
+                *index += 1;
+            }
+            continue;
+        }
+        if starts_with_at(graphemes, *index, close) {
+            for _ in 0..close_len {
+                hightlighting.push(hightlighting::Type::String);
+            }
+            *index += close_len;
+            return true;
+        }
+        hightlighting.push(hightlighting::Type::String);
+        *index += 1;
+    }
+    false
+}
+
+fn hightlight_char(
+    index: &mut usize,
+    opts: &HighlightingOptions,
+    graphemes: &[&str],
+    hightlighting: &mut Vec<hightlighting::Type>,
+) -> bool {
+    if !opts.characters() || graphemes[*index] != "'" {
+        return false;
+    }
+    let closing = if graphemes.get(*index + 1) == Some(&"\\") {
+        *index + 3
+    } else {
+        *index + 2
+    };
+    if graphemes.get(closing) != Some(&"'") {
+        return false;
+    }
+    for _ in *index..=closing {
+        hightlighting.push(hightlighting::Type::Character);
+    }
+    *index = closing + 1;
+    true
+}
+
+fn hightlight_number(
+    index: &mut usize,
+    opts: &HighlightingOptions,
+    graphemes: &[&str],
+    hightlighting: &mut Vec<hightlighting::Type>,
+) -> bool {
+    let is_digit = |grapheme: &str| grapheme.chars().all(|c| c.is_ascii_digit());
+    if !opts.numbers() || !is_digit(graphemes[*index]) {
+        return false;
+    }
+    if *index > 0 && !is_separator(graphemes[*index - 1]) {
+        return false;
+    }
+    while let Some(grapheme) = graphemes.get(*index) {
+        let continues = grapheme.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            || (*grapheme == "." && graphemes.get(*index + 1).map_or(false, |next| is_digit(next)));
+        if !continues {
+            break;
+        }
+        hightlighting.push(hightlighting::Type::Number);
+        *index += 1;
+    }
+    true
+}
+
+fn hightlight_keywords(
+    index: &mut usize,
+    keywords: &[&str],
+    hightlighting_type: hightlighting::Type,
+    graphemes: &[&str],
+    hightlighting: &mut Vec<hightlighting::Type>,
+) -> bool {
+    if *index > 0 && !is_separator(graphemes[*index - 1]) {
+        return false;
+    }
+    for keyword in keywords {
+        if !starts_with_at(graphemes, *index, keyword) {
+            continue;
+        }
+        let len = keyword[..].graphemes(true).count();
+        if graphemes.get(*index + len).map_or(true, |next| is_separator(next)) {
+            for _ in 0..len {
+                hightlighting.push(hightlighting_type);
+            }
+            *index += len;
+            return true;
+        }
+    }
+    false
+}
+
 // bukausbrayvbvuybsuybviuybsdruybvyubvrby
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_wrap_where_the_next_grapheme_would_not_fit() {
+        for (text, width, at_words, starts) in [
+            ("", 3, false, vec![0]),
+            ("abcdefg", 3, false, vec![0, 3, 6]),
+            // Exactly filling the last line leaves an empty one after it.
+            ("abcdef", 3, false, vec![0, 3, 6]),
+            ("one two", 5, false, vec![0, 5]),
+            ("one two", 5, true, vec![0, 4]),
+            ("one two three", 8, true, vec![0, 8]),
+            ("ab \u{4e16}", 4, false, vec![0, 3]),
+            ("a\u{4e16}b", 2, false, vec![0, 1, 2]),
+            // A grapheme wider than the line still gets one to itself.
+            ("\u{4e16}\u{4e16}", 1, false, vec![0, 1, 2]),
+            ("\tab", 4, false, vec![0, 1]),
+            ("a\tb", 3, false, vec![0, 1, 2]),
+        ] {
+            assert_eq!(Row::from(text).wrap(width, 4, at_words), starts, "wrapping {:?} at {}", text, width);
+        }
+    }
+
+    #[test]
+    fn columns_and_indexes_map_both_ways() {
+        let row = Row::from("a\t\u{4e16}b");
+        for (index, column, width) in [(0, 0, 1), (1, 1, 3), (2, 4, 2), (3, 6, 1), (4, 7, 1)] {
+            assert_eq!(row.width_to(index, 4), column, "column of {}", index);
+            assert_eq!(row.width_at(index, 4), width, "width of {}", index);
+        }
+        for (column, index) in [(0, 0), (1, 1), (3, 1), (4, 2), (5, 2), (6, 3), (7, 4), (20, 4)] {
+            assert_eq!(row.index_at(column, 4), index, "index at column {}", column);
+        }
+        assert_eq!(row.width_to(2, 8), 8);
+        assert_eq!(row.index_at(7, 8), 1);
+    }
+}
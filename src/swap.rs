@@ -0,0 +1,268 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process;
+
+const HEADER: &str = "hecto swap";
+// The swap file's permissions when the file being edited doesn't exist.
+const DEFAULT_MODE: u32 = 0o600;
+
+/// A copy of unsaved changes kept next to the file being edited, so they
+/// can be recovered if the editor dies before saving. The file starts with
+/// a header line naming the process that wrote it, followed by the text.
+pub struct SwapFile {
+    file: PathBuf,
+    path: PathBuf,
+}
+
+/// What was found in an existing swap file.
+pub struct Recovered {
+    pub pid: u32,
+    pub text: String,
+}
+
+impl Recovered {
+    /// Whether the process that wrote the swap file is still running, in
+    /// which case the file is being edited elsewhere rather than abandoned.
+    /// Only the pid is checked, so an unrelated process that has since been
+    /// given the same pid, or one on another machine sharing the directory,
+    /// makes an abandoned file look owned. It is then left alone, with a
+    /// warning naming the pid, rather than offered for recovery.
+    pub fn owner_is_running(&self) -> bool {
+        if self.pid == process::id() {
+            return false;
+        }
+        // SAFETY: signal 0 only checks that the process exists and can be
+        // signalled; nothing is sent and no memory is passed.
+        let result = unsafe { libc::kill(self.pid as libc::pid_t, 0) };
+        result == 0 || Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+}
+
+impl SwapFile {
+    /// The swap file for `file_name`: a hidden file in the same directory.
+    pub fn for_file(file_name: &str) -> Self {
+        let path = Path::new(file_name);
+        let name = path
+            .file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        Self {
+            file: path.to_path_buf(),
+            path: dir.join(format!(".{}.hecto.swp", name)),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the swap file, if there is one.
+    pub fn read(&self) -> Result<Option<Recovered>, Error> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        let (header, text) = contents.split_once('\n').unwrap_or((&contents, ""));
+        let pid = header
+            .strip_prefix(HEADER)
+            .and_then(|pid| pid.trim().parse().ok())
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{} is not a swap file", self.path.display()),
+                )
+            })?;
+        Ok(Some(Recovered {
+            pid,
+            text: text.to_string(),
+        }))
+    }
+
+    /// Writes the swap file with the same permissions as the file being
+    /// edited, so no one can read the changes who couldn't read the file.
+    /// It is written in full beside the swap file and then renamed over it,
+    /// so a crash while writing leaves the previous copy intact.
+    pub fn write(&self, text: &str) -> Result<(), Error> {
+        let mode = fs::metadata(&self.file)
+            .map_or(DEFAULT_MODE, |metadata| metadata.permissions().mode() & 0o777);
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(".tmp");
+        match fs::remove_file(&temp_path) {
+            Err(error) if error.kind() != ErrorKind::NotFound => return Err(error),
+            _ => (),
+        }
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(mode)
+            .open(&temp_path)?;
+        // The umask may have taken bits off the mode it was created with.
+        file.set_permissions(fs::Permissions::from_mode(mode))?;
+        file.write_all(format!("{} {}\n{}", HEADER, process::id(), text).as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temp_path, &self.path)
+    }
+
+    pub fn remove(&self) -> Result<(), Error> {
+        match fs::remove_file(&self.path) {
+            Err(error) if error.kind() != ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Compares two texts line by line, returning each line prefixed with
+/// `-` if it is only in `old`, `+` if it is only in `new`, or a space if it
+/// is in both. Very large changes are shown as a removal and an addition.
+pub fn diff(old: &str, new: &str) -> Vec<String> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut result: Vec<String> = old[..prefix].iter().map(|line| format!("  {}", line)).collect();
+    if old_middle.len().saturating_mul(new_middle.len()) > MAX_DIFF_CELLS {
+        result.extend(old_middle.iter().map(|line| format!("- {}", line)));
+        result.extend(new_middle.iter().map(|line| format!("+ {}", line)));
+    } else {
+        result.extend(diff_lines(old_middle, new_middle));
+    }
+    result.extend(old[old.len() - suffix..].iter().map(|line| format!("  {}", line)));
+    result
+}
+
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+// A longest-common-subsequence diff of the lines that changed.
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<String> {
+    let width = new.len() + 1;
+    let mut lengths = vec![0_u32; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i * width + j] = if old[i] == new[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+    let mut result = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            result.push(format!("  {}", old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len()
+            && (j == new.len() || lengths[(i + 1) * width + j] >= lengths[i * width + j + 1])
+        {
+            result.push(format!("- {}", old[i]));
+            i += 1;
+        } else {
+            result.push(format!("+ {}", new[j]));
+            j += 1;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn swap_for(name: &str) -> SwapFile {
+        let path = env::temp_dir().join(format!("hecto-{}-{}", process::id(), name));
+        SwapFile::for_file(&path.to_string_lossy())
+    }
+
+    #[test]
+    fn swap_file_sits_beside_the_file() {
+        assert_eq!(SwapFile::for_file("dir/notes.txt").path(), Path::new("dir/.notes.txt.hecto.swp"));
+        assert_eq!(SwapFile::for_file("notes.txt").path(), Path::new(".notes.txt.hecto.swp"));
+    }
+
+    #[test]
+    fn header_names_the_writer() {
+        let swap = swap_for("header.txt");
+        assert!(swap.read().unwrap().is_none());
+        swap.write("first\nsecond\n").unwrap();
+        let recovered = swap.read().unwrap().unwrap();
+        assert_eq!(recovered.pid, process::id());
+        assert_eq!(recovered.text, "first\nsecond\n");
+        assert!(!recovered.owner_is_running());
+
+        fs::write(swap.path(), format!("{} 123\n", HEADER)).unwrap();
+        let recovered = swap.read().unwrap().unwrap();
+        assert_eq!((recovered.pid, recovered.text.as_str()), (123, ""));
+
+        for contents in ["not a swap file\ntext", "hecto swap\ntext", "hecto swap x1\n", ""] {
+            fs::write(swap.path(), contents).unwrap();
+            let error = swap.read().err().unwrap();
+            assert_eq!(error.kind(), ErrorKind::InvalidData, "reading {:?}", contents);
+        }
+        swap.remove().unwrap();
+        swap.remove().unwrap();
+        assert!(!swap.path().exists());
+    }
+
+    #[test]
+    fn swap_file_is_only_as_readable_as_the_file() {
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        let swap = swap_for("permissions.txt");
+        swap.write("new file").unwrap();
+        assert_eq!(mode(swap.path()), DEFAULT_MODE);
+
+        fs::write(&swap.file, "secret").unwrap();
+        fs::set_permissions(&swap.file, fs::Permissions::from_mode(0o640)).unwrap();
+        swap.write("changed secret").unwrap();
+        assert_eq!(mode(swap.path()), 0o640);
+        assert_eq!(swap.read().unwrap().unwrap().text, "changed secret");
+        fs::remove_file(&swap.file).unwrap();
+        swap.remove().unwrap();
+    }
+
+    #[test]
+    fn running_owner_is_found() {
+        let mut child = process::Command::new("sleep").arg("10").spawn().unwrap();
+        let recovered = Recovered {
+            pid: child.id(),
+            text: String::new(),
+        };
+        assert!(recovered.owner_is_running());
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(!recovered.owner_is_running());
+    }
+
+    #[test]
+    fn diff_marks_changed_lines() {
+        assert_eq!(diff("a\nb\nc", "a\nb\nc"), ["  a", "  b", "  c"]);
+        assert_eq!(diff("a\nb\nc", "a\nx\nc"), ["  a", "- b", "+ x", "  c"]);
+        assert_eq!(diff("a\nc", "a\nb\nc"), ["  a", "+ b", "  c"]);
+        assert_eq!(diff("a\nb\nc\nd", "b\nd\ne"), ["- a", "  b", "- c", "  d", "+ e"]);
+        assert_eq!(diff("", "new"), ["+ new"]);
+        assert_eq!(diff("old", ""), ["- old"]);
+        assert_eq!(diff("a\na", "a"), ["  a", "- a"]);
+    }
+
+    #[test]
+    fn large_changes_are_a_removal_and_an_addition() {
+        let old: Vec<String> = (0..2001).map(|line| format!("old {}", line)).collect();
+        let new: Vec<String> = (0..2001).map(|line| format!("new {}", line)).collect();
+        let lines = diff(&old.join("\n"), &new.join("\n"));
+        assert_eq!(lines.len(), 4002);
+        assert_eq!(lines[2000], "- old 2000");
+        assert_eq!(lines[2001], "+ new 0");
+    }
+}
@@ -1,43 +1,140 @@
 use crate::Position;
-use std::io::{self, stdout, Write};
-use termion::color;
-use termion::event::Key;
-use termion::input::TermRead;
+use std::io::{self, stdout, Read, Write};
+use termion::event::{self, Event, Key};
 use termion::raw::{IntoRawMode, RawTerminal};
+
+// How long to wait for input before checking whether the window was resized.
+const RESIZE_POLL_MS: i32 = 100;
+
+#[derive(Clone, Copy)]
 pub struct Size {
     pub width: u16,
     pub height: u16,
 }
 
+/// A key press along with whether shift was held for it. termion only
+/// reports shift for characters, so arrows and Home/End are decoded here.
+pub struct Input {
+    pub key: Key,
+    pub shift: bool,
+}
+
+/// Everything the editor needs from a screen and keyboard, so it can run on
+/// a real terminal or on an in-memory `VirtualScreen`.
+pub trait Backend {
+    /// The area available for rows, without the status bar and message.
+    fn size(&self) -> Size;
+
+    /// Waits for the next key. Returns `None` instead if the window was
+    /// resized in the meantime, so the caller can redraw.
+    fn read_input(&mut self) -> Result<Option<Input>, std::io::Error>;
+
+    /// Whether `read_input` would return straight away, with a key or
+    /// because the window was resized.
+    fn input_pending(&mut self) -> Result<bool, std::io::Error>;
+
+    fn read_key(&mut self) -> Result<Option<Key>, std::io::Error> {
+        Ok(self.read_input()?.map(|input| input.key))
+    }
+
+    fn write(&mut self, text: &str);
+
+    fn flash(&mut self) -> Result<(), std::io::Error>;
+
+    fn clear_screen(&mut self);
+
+    fn cursor_pos(&mut self, pos: &Position);
+
+    fn cursor_hide(&mut self);
+
+    fn cursor_show(&mut self);
+
+    /// Asks the terminal to put base64-encoded text on the system clipboard.
+    fn set_clipboard(&mut self, encoded: &str);
+}
+
 pub struct Terminal {
     size: Size,
     _stdout: RawTerminal<std::io::Stdout>,
+    pending: Vec<u8>,
 }
 
 impl Terminal {
     pub fn default() -> Result<Self, std::io::Error> {
         let size = termion::terminal_size()?;
-        Ok(Self {
+        let mut terminal = Self {
             size: Size {
-                width: size.0,
-                height: size.1.saturating_sub(2),
+                width: 0,
+                height: 0,
             },
             _stdout: stdout().into_raw_mode()?,
-        })
+            pending: Vec::new(),
+        };
+        terminal.resize(size.0, size.1);
+        Ok(terminal)
     }
-    pub fn size(&self) -> &Size {
-        &self.size
+
+    /// Sets the size from the full window dimensions, leaving two lines for
+    /// the status bar and message. Returns whether the size changed.
+    pub fn resize(&mut self, width: u16, height: u16) -> bool {
+        let height = height.saturating_sub(2);
+        if self.size.width == width && self.size.height == height {
+            return false;
+        }
+        self.size = Size { width, height };
+        true
     }
+}
 
-    pub fn flash() -> Result<(), std::io::Error> {
+impl Backend for Terminal {
+    fn size(&self) -> Size {
+        self.size
+    }
+
+    fn read_input(&mut self) -> Result<Option<Input>, std::io::Error> {
+        loop {
+            if self.pending.is_empty() {
+                if !wait_for_stdin(RESIZE_POLL_MS)? {
+                    let size = termion::terminal_size()?;
+                    if self.resize(size.0, size.1) {
+                        return Ok(None);
+                    }
+                    continue;
+                }
+                let mut buffer = [0; 256];
+                let read = io::stdin().lock().read(&mut buffer)?;
+                self.pending.extend_from_slice(&buffer[..read]);
+                continue;
+            }
+            let (input, consumed) = parse_input(&self.pending);
+            self.pending.drain(..consumed);
+            if let Some(input) = input {
+                return Ok(Some(input));
+            }
+        }
+    }
+
+    fn input_pending(&mut self) -> Result<bool, std::io::Error> {
+        if !self.pending.is_empty() || wait_for_stdin(0)? {
+            return Ok(true);
+        }
+        let (width, height) = termion::terminal_size()?;
+        Ok(self.size.width != width || self.size.height != height.saturating_sub(2))
+    }
+
+    fn write(&mut self, text: &str) {
+        print!("{}", text);
+    }
+
+    fn flash(&mut self) -> Result<(), std::io::Error> {
         io::stdout().flush()
     }
 
-    pub fn clear_screen() {
+    fn clear_screen(&mut self) {
         print!("{}", termion::clear::All);
     }
 
-    pub fn cursor_pos(pos: &Position) {
+    fn cursor_pos(&mut self, pos: &Position) {
         let Position { mut x, mut y } = pos;
         x = x.saturating_add(1);
         y = y.saturating_add(1);
@@ -46,39 +143,68 @@ impl Terminal {
         print!("{}", termion::cursor::Goto(x, y));
     }
 
-    pub fn cursor_hide() {
+    fn cursor_hide(&mut self) {
         print!("{}", termion::cursor::Hide);
     }
 
-    pub fn cursor_show() {
+    fn cursor_show(&mut self) {
         print!("{}", termion::cursor::Show);
     }
 
-    pub fn clear_current_line() {
-        print!("{}", termion::clear::CurrentLine);
+    fn set_clipboard(&mut self, encoded: &str) {
+        print!("\x1b]52;c;{}\x07", encoded);
     }
+}
 
-    pub fn read_key() -> Result<Key, std::io::Error> {
-        loop {
-            if let Some(key) = io::stdin().lock().keys().next() {
-                return key;
-            }
+// Returns whether stdin has input within `timeout_ms` milliseconds.
+fn wait_for_stdin(timeout_ms: i32) -> Result<bool, std::io::Error> {
+    let mut fds = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: `fds` is a single valid `pollfd` that outlives the call, and
+    // the count of 1 tells `poll` not to read past it.
+    let ready = unsafe { libc::poll(&mut fds, 1, timeout_ms) };
+    if ready < 0 {
+        let error = std::io::Error::last_os_error();
+        if error.kind() == std::io::ErrorKind::Interrupted {
+            return Ok(false);
         }
+        return Err(error);
     }
+    Ok(ready > 0)
+}
 
-    pub fn set_bg_color(color: color::Rgb) {
-        print!("{}", color::Bg(color));
+// Parses one event from the front of `bytes`, returning it (if it is a key)
+// and how many bytes it took up.
+fn parse_input(bytes: &[u8]) -> (Option<Input>, usize) {
+    if bytes == b"\x1b" {
+        return (
+            Some(Input {
+                key: Key::Esc,
+                shift: false,
+            }),
+            1,
+        );
     }
-
-    pub fn unset_bg_color() {
-        print!("{}", color::Bg(color::Reset));
+    if bytes.starts_with(b"\x1b[1;2") && bytes.len() >= 6 {
+        let key = match bytes[5] {
+            b'A' => Some(Key::Up),
+            b'B' => Some(Key::Down),
+            b'C' => Some(Key::Right),
+            b'D' => Some(Key::Left),
+            b'H' => Some(Key::Home),
+            b'F' => Some(Key::End),
+            _ => None,
+        };
+        return (key.map(|key| Input { key, shift: true }), 6);
     }
-
-    pub fn set_fg_color(color: color::Rgb) {
-        print!("{}", color::Fg(color));
-    }
-
-    pub fn unset_fg_color() {
-        print!("{}", color::Fg(color::Reset));
+    let mut rest = bytes[1..].iter();
+    let parsed = event::parse_event(bytes[0], &mut rest.by_ref().map(|byte| Ok(*byte)));
+    let consumed = bytes.len() - rest.len();
+    match parsed {
+        Ok(Event::Key(key)) => (Some(Input { key, shift: false }), consumed),
+        _ => (None, consumed),
     }
 }
@@ -0,0 +1,636 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::mem;
+use std::ops::{Index, IndexMut};
+use std::slice;
+use unicode_segmentation::{Graphemes, UnicodeSegmentation};
+use unicode_width::UnicodeWidthStr;
+
+const MAX_CHUNK_BYTES: usize = 1024;
+const MAX_BLOCK_LEN: usize = 1024;
+
+/// A line of text stored as a list of chunks of whole graphemes, a rope
+/// only one level deep. Each chunk caches how many graphemes it holds and
+/// how wide it is, and the text keeps where each chunk starts, so finding
+/// a grapheme is a binary search and an edit only rewrites the chunk it
+/// lands in. Being one level, an edit still renumbers the chunks after it,
+/// which stays cheap for the length of a line.
+#[derive(Default, Clone)]
+pub struct Text {
+    chunks: Vec<Chunk>,
+    // The grapheme and the byte offset each chunk starts at.
+    starts: Vec<usize>,
+    byte_starts: Vec<usize>,
+    len: usize,
+}
+
+#[derive(Clone)]
+struct Chunk {
+    text: String,
+    len: usize,
+    // The columns it takes up, unless it has a tab, whose width depends on
+    // the column the chunk starts at.
+    width: Option<usize>,
+}
+
+impl Chunk {
+    fn new(text: String) -> Self {
+        let mut len = 0;
+        let mut width = Some(0);
+        for grapheme in text.graphemes(true) {
+            len += 1;
+            width = match grapheme {
+                "\t" => None,
+                _ => width.map(|width| width + grapheme.width()),
+            };
+        }
+        Self { text, len, width }
+    }
+
+    fn width_from(&self, column: usize, tab_width: usize) -> usize {
+        self.width.unwrap_or_else(|| {
+            self.text
+                .graphemes(true)
+                .fold(column, |column, grapheme| column + grapheme_width(grapheme, column, tab_width))
+                - column
+        })
+    }
+
+    // The byte offset of grapheme `index`, or the chunk's length past it.
+    fn byte_index(&self, index: usize) -> usize {
+        self.text
+            .grapheme_indices(true)
+            .nth(index)
+            .map_or(self.text.len(), |(byte_index, _)| byte_index)
+    }
+}
+
+impl From<&str> for Text {
+    fn from(text: &str) -> Self {
+        Self::from_chunks(chunks_of(text))
+    }
+}
+
+impl fmt::Display for Text {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.chunks().try_for_each(|chunk| f.write_str(chunk))
+    }
+}
+
+impl Text {
+    fn from_chunks(chunks: Vec<Chunk>) -> Self {
+        let mut starts = Vec::with_capacity(chunks.len());
+        let mut byte_starts = Vec::with_capacity(chunks.len());
+        let mut len = 0;
+        let mut bytes = 0;
+        for chunk in &chunks {
+            starts.push(len);
+            byte_starts.push(bytes);
+            len += chunk.len;
+            bytes += chunk.text.len();
+        }
+        Self {
+            chunks,
+            starts,
+            byte_starts,
+            len,
+        }
+    }
+
+    /// The number of graphemes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The pieces the text is stored in, in order.
+    pub fn chunks(&self) -> impl Iterator<Item = &str> {
+        self.chunks.iter().map(|chunk| chunk.text.as_str())
+    }
+
+    /// The whole text as one string, only copied if it is in several chunks.
+    pub fn as_str(&self) -> Cow<'_, str> {
+        match self.chunks.as_slice() {
+            [] => Cow::Borrowed(""),
+            [chunk] => Cow::Borrowed(&chunk.text),
+            _ => Cow::Owned(self.to_string()),
+        }
+    }
+
+    pub fn graphemes(&self) -> impl Iterator<Item = &str> {
+        self.graphemes_from(0)
+    }
+
+    pub fn graphemes_from(&self, at: usize) -> impl Iterator<Item = &str> {
+        let (chunk, offset) = self.locate(at);
+        self.chunks[chunk..]
+            .iter()
+            .flat_map(|chunk| chunk.text.graphemes(true))
+            .skip(offset)
+    }
+
+    pub fn grapheme(&self, at: usize) -> Option<&str> {
+        let (chunk, offset) = self.locate(at);
+        self.chunks.get(chunk)?.text.graphemes(true).nth(offset)
+    }
+
+    /// The graphemes from `start` up to (not including) `end`.
+    pub fn slice(&self, start: usize, end: usize) -> String {
+        self.graphemes_from(start)
+            .take(end.saturating_sub(start))
+            .collect()
+    }
+
+    /// The byte offset of grapheme `at` in the whole text, or `None` if it
+    /// is past the end.
+    pub fn byte_index(&self, at: usize) -> Option<usize> {
+        if at > self.len {
+            return None;
+        }
+        let (chunk, offset) = self.locate_in_chunk(at);
+        Some(self.chunks.get(chunk).map_or(0, |found| self.byte_starts[chunk] + found.byte_index(offset)))
+    }
+
+    /// How many graphemes end at or before byte offset `byte_index`.
+    pub fn grapheme_index(&self, byte_index: usize) -> usize {
+        let chunk = self.byte_starts.partition_point(|start| *start <= byte_index).saturating_sub(1);
+        self.chunks.get(chunk).map_or(0, |found| {
+            let start = self.byte_starts[chunk];
+            self.starts[chunk]
+                + found
+                    .text
+                    .grapheme_indices(true)
+                    .take_while(|(at, grapheme)| start + at + grapheme.len() <= byte_index)
+                    .count()
+        })
+    }
+
+    /// Inserts `text` before grapheme `at`, or at the end if `at` is past it.
+    pub fn insert(&mut self, at: usize, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        if self.chunks.is_empty() {
+            *self = Self::from(text);
+            return;
+        }
+        let (chunk, offset) = self.locate_in_chunk(at);
+        let mut edited = self.chunks[chunk].text.clone();
+        edited.insert_str(self.chunks[chunk].byte_index(offset), text);
+        self.replace_chunks(chunk, chunk + 1, &edited);
+    }
+
+    /// Removes the graphemes from `start` up to (not including) `end`.
+    pub fn remove(&mut self, start: usize, end: usize) {
+        let end = end.min(self.len);
+        if start >= end {
+            return;
+        }
+        let (first, start_offset) = self.locate(start);
+        let (last, end_offset) = self.locate_in_chunk(end);
+        let mut edited = String::from(&self.chunks[first].text[..self.chunks[first].byte_index(start_offset)]);
+        edited.push_str(&self.chunks[last].text[self.chunks[last].byte_index(end_offset)..]);
+        self.replace_chunks(first, last + 1, &edited);
+    }
+
+    /// Splits the text in two at grapheme `at`, returning the second half.
+    pub fn split_off(&mut self, at: usize) -> Self {
+        if at >= self.len {
+            return Self::default();
+        }
+        let (chunk, offset) = self.locate(at);
+        let mut rest = self.chunks.split_off(chunk);
+        let byte_index = rest[0].byte_index(offset);
+        if byte_index > 0 {
+            let head = rest[0].text[..byte_index].to_string();
+            rest[0] = Chunk::new(rest[0].text[byte_index..].to_string());
+            self.chunks.push(Chunk::new(head));
+        }
+        *self = Self::from_chunks(mem::take(&mut self.chunks));
+        Self::from_chunks(rest)
+    }
+
+    pub fn append(&mut self, other: Self) {
+        let mut chunks = other.chunks;
+        if chunks.is_empty() {
+            return;
+        }
+        if let Some(last) = self.chunks.pop() {
+            // The graphemes either side of the join may combine.
+            let joined = last.text + &chunks[0].text;
+            chunks.splice(0..1, chunks_of(&joined));
+        }
+        self.chunks.append(&mut chunks);
+        *self = Self::from_chunks(mem::take(&mut self.chunks));
+    }
+
+    /// Each grapheme with its index, the screen column it starts at and its
+    /// width, with tabs reaching the next multiple of `tab_width`.
+    pub fn columns(&self, tab_width: usize) -> Columns<'_> {
+        self.seek(tab_width, |_, _| false)
+    }
+
+    /// Like `columns`, but starting from the chunk holding grapheme `index`.
+    pub fn columns_from_index(&self, index: usize, tab_width: usize) -> Columns<'_> {
+        self.seek(tab_width, |end_index, _| end_index <= index)
+    }
+
+    /// Like `columns`, but starting from the chunk covering screen column
+    /// `column`.
+    pub fn columns_from_column(&self, column: usize, tab_width: usize) -> Columns<'_> {
+        self.seek(tab_width, |_, end_column| end_column <= column)
+    }
+
+    // Skips whole chunks while `skip` says everything up to the index and
+    // column they end at comes before what is wanted.
+    fn seek<F>(&self, tab_width: usize, skip: F) -> Columns<'_>
+    where
+        F: Fn(usize, usize) -> bool,
+    {
+        let tab_width = tab_width.max(1);
+        let mut index = 0;
+        let mut column = 0;
+        let mut skipped = 0;
+        for chunk in &self.chunks {
+            let end_column = column + chunk.width_from(column, tab_width);
+            if !skip(index + chunk.len, end_column) {
+                break;
+            }
+            index += chunk.len;
+            column = end_column;
+            skipped += 1;
+        }
+        Columns {
+            chunks: self.chunks[skipped..].iter(),
+            graphemes: None,
+            index,
+            column,
+            tab_width,
+        }
+    }
+
+    // The chunk holding grapheme `at` and its offset within it; past the
+    // end, that is the number of chunks.
+    fn locate(&self, at: usize) -> (usize, usize) {
+        if at >= self.len {
+            return (self.chunks.len(), at - self.len);
+        }
+        let chunk = self.starts.partition_point(|start| *start <= at) - 1;
+        (chunk, at - self.starts[chunk])
+    }
+
+    // The chunk holding grapheme `at` and its offset within it, where the
+    // end of the text is the end of the last chunk.
+    fn locate_in_chunk(&self, at: usize) -> (usize, usize) {
+        match self.chunks.last() {
+            Some(last) if at >= self.len => (self.chunks.len() - 1, last.len),
+            _ => self.locate(at),
+        }
+    }
+
+    // Replaces chunks `start..end` with `text`, re-chunking it together
+    // with the chunks either side, whose graphemes may combine with it.
+    fn replace_chunks(&mut self, start: usize, end: usize, text: &str) {
+        let from = start.saturating_sub(1);
+        let to = (end + 1).min(self.chunks.len());
+        let mut joined = String::new();
+        if from < start {
+            joined.push_str(&self.chunks[from].text);
+        }
+        joined.push_str(text);
+        if end < to {
+            joined.push_str(&self.chunks[end].text);
+        }
+        self.chunks.splice(from..to, chunks_of(&joined));
+        *self = Self::from_chunks(mem::take(&mut self.chunks));
+    }
+}
+
+/// An iterator over the graphemes of a `Text` with where they go on screen;
+/// see `Text::columns`.
+pub struct Columns<'a> {
+    chunks: slice::Iter<'a, Chunk>,
+    graphemes: Option<Graphemes<'a>>,
+    index: usize,
+    column: usize,
+    tab_width: usize,
+}
+
+impl<'a> Columns<'a> {
+    /// The column after the last grapheme returned.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+}
+
+impl<'a> Iterator for Columns<'a> {
+    type Item = (usize, &'a str, usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(grapheme) = self.graphemes.as_mut().and_then(Iterator::next) {
+                let width = grapheme_width(grapheme, self.column, self.tab_width);
+                let item = (self.index, grapheme, self.column, width);
+                self.index += 1;
+                self.column += width;
+                return Some(item);
+            }
+            self.graphemes = Some(self.chunks.next()?.text.graphemes(true));
+        }
+    }
+}
+
+fn grapheme_width(grapheme: &str, column: usize, tab_width: usize) -> usize {
+    if grapheme == "\t" {
+        tab_width - column % tab_width
+    } else {
+        grapheme.width()
+    }
+}
+
+// Cuts `text` into chunks of whole graphemes of up to `MAX_CHUNK_BYTES`,
+// unless a single grapheme is longer.
+fn chunks_of(text: &str) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    for (at, grapheme) in text.grapheme_indices(true) {
+        if at + grapheme.len() - start > MAX_CHUNK_BYTES && at > start {
+            chunks.push(Chunk::new(text[start..at].to_string()));
+            start = at;
+        }
+    }
+    if start < text.len() {
+        chunks.push(Chunk::new(text[start..].to_string()));
+    }
+    chunks
+}
+
+/// A list kept in blocks of up to `MAX_BLOCK_LEN` items, so inserting or
+/// removing one only moves the rest of its block rather than everything
+/// after it.
+pub struct BlockList<T> {
+    blocks: Vec<Vec<T>>,
+    // The index of the first item in each block.
+    starts: Vec<usize>,
+    len: usize,
+}
+
+impl<T> Default for BlockList<T> {
+    fn default() -> Self {
+        Self {
+            blocks: Vec::new(),
+            starts: Vec::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<T> FromIterator<T> for BlockList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = Self::default();
+        for item in iter {
+            list.push(item);
+        }
+        list
+    }
+}
+
+impl<T> Index<usize> for BlockList<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        let len = self.len;
+        self.get(index)
+            .unwrap_or_else(|| panic!("index {} out of range for length {}", index, len))
+    }
+}
+
+impl<T> IndexMut<usize> for BlockList<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        let len = self.len;
+        self.get_mut(index)
+            .unwrap_or_else(|| panic!("index {} out of range for length {}", index, len))
+    }
+}
+
+impl<T> BlockList<T> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len {
+            return None;
+        }
+        let (block, offset) = self.locate(index);
+        self.blocks[block].get(offset)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.len {
+            return None;
+        }
+        let (block, offset) = self.locate(index);
+        self.blocks[block].get_mut(offset)
+    }
+
+    /// Inserts `item` at `index`, panicking if that is past the end.
+    pub fn insert(&mut self, index: usize, item: T) {
+        assert!(index <= self.len, "index {} out of range for length {}", index, self.len);
+        if self.blocks.is_empty() {
+            self.blocks.push(Vec::with_capacity(MAX_BLOCK_LEN));
+            self.starts.push(0);
+        }
+        let (block, offset) = self.locate(index);
+        self.blocks[block].insert(offset, item);
+        self.len += 1;
+        for start in &mut self.starts[block + 1..] {
+            *start += 1;
+        }
+        if self.blocks[block].len() > MAX_BLOCK_LEN {
+            let half = self.blocks[block].len() / 2;
+            let rest = self.blocks[block].split_off(half);
+            self.blocks.insert(block + 1, rest);
+            self.starts.insert(block + 1, self.starts[block] + half);
+        }
+    }
+
+    /// Removes and returns the item at `index`, panicking if there is none.
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len, "index {} out of range for length {}", index, self.len);
+        let (block, offset) = self.locate(index);
+        let item = self.blocks[block].remove(offset);
+        self.len -= 1;
+        for start in &mut self.starts[block + 1..] {
+            *start -= 1;
+        }
+        if self.blocks[block].is_empty() {
+            self.blocks.remove(block);
+            self.starts.remove(block);
+        }
+        item
+    }
+
+    pub fn push(&mut self, item: T) {
+        self.insert(self.len, item);
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        Some(self.remove(self.len - 1))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.blocks.iter().flatten()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.blocks.iter_mut().flatten()
+    }
+
+    /// The items from `index` on.
+    pub fn iter_from(&self, index: usize) -> impl Iterator<Item = &T> {
+        let (block, offset) = self.locate(index);
+        self.blocks.iter().skip(block).enumerate().flat_map(move |(skipped, items)| {
+            let from = if skipped == 0 { offset.min(items.len()) } else { 0 };
+            items[from..].iter()
+        })
+    }
+
+    pub fn iter_mut_from(&mut self, index: usize) -> impl Iterator<Item = &mut T> {
+        let (block, offset) = self.locate(index);
+        self.blocks.iter_mut().skip(block).enumerate().flat_map(move |(skipped, items)| {
+            let from = if skipped == 0 { offset.min(items.len()) } else { 0 };
+            items[from..].iter_mut()
+        })
+    }
+
+    // The block holding item `index` and its offset within it; the end of
+    // the list is at the end of the last block.
+    fn locate(&self, index: usize) -> (usize, usize) {
+        let block = self.starts.partition_point(|start| *start <= index).saturating_sub(1);
+        (block, index - self.starts.get(block).copied().unwrap_or(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Checks `text` holds `expected`, in chunks of whole graphemes no
+    // bigger than they need to be, with the cached starts up to date.
+    fn check(text: &Text, expected: &str) {
+        assert_eq!(text.to_string(), expected);
+        assert_eq!(text.len(), expected.graphemes(true).count());
+        let graphemes: Vec<&str> = text.chunks().flat_map(|chunk| chunk.graphemes(true)).collect();
+        assert_eq!(graphemes, expected.graphemes(true).collect::<Vec<_>>());
+        let mut len = 0;
+        let mut bytes = 0;
+        for (index, chunk) in text.chunks.iter().enumerate() {
+            assert!(chunk.text.len() <= MAX_CHUNK_BYTES || chunk.len == 1);
+            assert_eq!((text.starts[index], text.byte_starts[index]), (len, bytes));
+            len += chunk.len;
+            bytes += chunk.text.len();
+        }
+    }
+
+    #[test]
+    fn edits_across_chunk_boundaries() {
+        let mut expected: String = (0..3000).map(|at| char::from(b'a' + (at % 26) as u8)).collect();
+        let mut text = Text::from(expected.as_str());
+        assert_eq!(text.chunks().count(), 3);
+        check(&text, &expected);
+
+        text.insert(MAX_CHUNK_BYTES, "XYZ");
+        expected.insert_str(MAX_CHUNK_BYTES, "XYZ");
+        check(&text, &expected);
+
+        text.remove(1000, 2100);
+        expected.replace_range(1000..2100, "");
+        check(&text, &expected);
+        assert_eq!(text.grapheme(1000), Some(&expected[1000..1001]));
+        assert_eq!(text.slice(995, 1005), expected[995..1005]);
+
+        text.insert(text.len() + 5, "end");
+        expected.push_str("end");
+        check(&text, &expected);
+    }
+
+    #[test]
+    fn multibyte_graphemes_stay_whole_at_chunk_edges() {
+        // Five bytes each, so chunk edges don't fall on a multiple of 1024.
+        let accented = "e\u{301}\u{327}";
+        let mut expected = accented.repeat(500);
+        let mut text = Text::from(expected.as_str());
+        check(&text, &expected);
+        assert!(text.chunks().count() > 1);
+
+        // A combining mark at the start of a chunk joins the grapheme that
+        // ends the chunk before it.
+        let second = text.starts[1];
+        text.insert(second, "\u{308}");
+        let byte_index = expected.grapheme_indices(true).nth(second).unwrap().0;
+        expected.insert(byte_index, '\u{308}');
+        check(&text, &expected);
+        assert_eq!(text.len(), 500);
+        assert_eq!(text.grapheme(second - 1), Some("e\u{301}\u{327}\u{308}"));
+
+        assert_eq!(text.byte_index(second), Some(byte_index + 2));
+        assert_eq!(text.grapheme_index(byte_index + 2), second);
+        assert_eq!(text.grapheme_index(byte_index + 1), second - 1);
+        assert_eq!(text.byte_index(text.len()), Some(expected.len()));
+        assert_eq!(text.byte_index(text.len() + 1), None);
+    }
+
+    #[test]
+    fn split_off_and_append() {
+        let expected: String = "😀 wide ".repeat(300);
+        let graphemes: Vec<&str> = expected.graphemes(true).collect();
+        for at in [0, 1, 150, 1024 / 4, 1200, graphemes.len() - 1, graphemes.len()] {
+            let mut text = Text::from(expected.as_str());
+            let rest = text.split_off(at);
+            check(&text, &graphemes[..at].concat());
+            check(&rest, &graphemes[at..].concat());
+            text.append(rest);
+            check(&text, &expected);
+        }
+
+        let mut text = Text::from("cafe");
+        text.append(Text::from("\u{301} au lait"));
+        check(&text, "cafe\u{301} au lait");
+        assert_eq!(text.grapheme(3), Some("e\u{301}"));
+    }
+
+    #[test]
+    fn block_list_finds_items_across_blocks() {
+        let mut expected: Vec<usize> = (0..MAX_BLOCK_LEN * 3).collect();
+        let mut list: BlockList<usize> = expected.iter().copied().collect();
+        assert!(list.blocks.len() > 1);
+        for (at, item) in [(0, 10_000), (MAX_BLOCK_LEN, 10_001), (MAX_BLOCK_LEN * 2 + 7, 10_002)] {
+            list.insert(at, item);
+            expected.insert(at, item);
+        }
+        for at in [MAX_BLOCK_LEN - 1, 5, MAX_BLOCK_LEN * 2] {
+            assert_eq!(list.remove(at), expected.remove(at));
+        }
+        assert_eq!(list.len(), expected.len());
+        for (index, item) in expected.iter().enumerate() {
+            assert_eq!(list[index], *item);
+        }
+        assert_eq!(list.get(expected.len()), None);
+        let from = MAX_BLOCK_LEN + 3;
+        assert!(list.iter_from(from).eq(expected[from..].iter()));
+        list[from] = 7;
+        assert_eq!(list.get(from), Some(&7));
+        assert_eq!(list.pop(), expected.pop());
+    }
+}
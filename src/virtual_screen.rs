@@ -0,0 +1,233 @@
+use crate::terminal::{Backend, Input, Size};
+use crate::Position;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind};
+use std::rc::Rc;
+use termion::event::Key;
+use unicode_width::UnicodeWidthChar;
+
+/// An in-memory screen and scripted keyboard for driving the editor without
+/// a terminal. Clones share the same screen, so a caller can hand one to the
+/// editor and keep another to feed keys and inspect what was drawn.
+#[derive(Clone)]
+pub struct VirtualScreen {
+    state: Rc<RefCell<ScreenState>>,
+}
+
+struct ScreenState {
+    width: usize,
+    height: usize,
+    // Each cell holds a character and any zero-width ones after it; the
+    // cell after a wide character is left empty.
+    cells: Vec<Vec<String>>,
+    cursor: Position,
+    inputs: VecDeque<Option<Input>>,
+    clipboard: Option<String>,
+}
+
+impl VirtualScreen {
+    /// Creates a blank screen of the full window size, including the two
+    /// lines the editor keeps for its status bar and message.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            state: Rc::new(RefCell::new(ScreenState {
+                width,
+                height,
+                cells: vec![vec![String::from(" "); width]; height],
+                cursor: Position::default(),
+                inputs: VecDeque::new(),
+                clipboard: None,
+            })),
+        }
+    }
+
+    pub fn push_key(&self, key: Key) {
+        self.push_input(Input { key, shift: false });
+    }
+
+    pub fn push_keys(&self, keys: &[Key]) {
+        for key in keys {
+            self.push_key(*key);
+        }
+    }
+
+    pub fn push_input(&self, input: Input) {
+        self.state.borrow_mut().inputs.push_back(Some(input));
+    }
+
+    /// Types every character of `text` as a key press.
+    pub fn type_text(&self, text: &str) {
+        for c in text.chars() {
+            self.push_key(Key::Char(c));
+        }
+    }
+
+    /// Queues a change of window size, reported to the editor the next time
+    /// it reads input.
+    pub fn resize(&self, width: usize, height: usize) {
+        let mut state = self.state.borrow_mut();
+        state.width = width;
+        state.height = height;
+        state.cells = vec![vec![String::from(" "); width]; height];
+        state.inputs.push_back(None);
+    }
+
+    /// Returns screen line `y` with trailing blanks removed.
+    pub fn line(&self, y: usize) -> String {
+        let state = self.state.borrow();
+        state
+            .cells
+            .get(y)
+            .map(|cells| cells.concat().trim_end().to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let height = self.state.borrow().height;
+        (0..height).map(|y| self.line(y)).collect()
+    }
+
+    pub fn cursor(&self) -> Position {
+        self.state.borrow().cursor.clone()
+    }
+
+    /// The base64 text last sent to the clipboard with OSC 52.
+    pub fn clipboard(&self) -> Option<String> {
+        self.state.borrow().clipboard.clone()
+    }
+}
+
+impl ScreenState {
+    fn put(&mut self, c: char) {
+        let Position { x, y } = self.cursor;
+        let width = c.width().unwrap_or(0);
+        let row = match self.cells.get_mut(y) {
+            Some(row) => row,
+            None => return,
+        };
+        if width == 0 {
+            if let Some(cell) = x.checked_sub(1).and_then(|x| row.get_mut(x)) {
+                cell.push(c);
+            }
+            return;
+        }
+        if let Some(cell) = row.get_mut(x) {
+            *cell = c.to_string();
+        }
+        for column in x.saturating_add(1)..x.saturating_add(width) {
+            if let Some(cell) = row.get_mut(column) {
+                cell.clear();
+            }
+        }
+        self.cursor.x = x.saturating_add(width);
+    }
+
+    // Applies the CSI sequence ending in `command`.
+    fn control(&mut self, command: char, params: &str) {
+        match command {
+            'H' => {
+                let mut numbers = params
+                    .split(';')
+                    .map(|number| number.parse::<usize>().unwrap_or(1));
+                let y = numbers.next().unwrap_or(1);
+                let x = numbers.next().unwrap_or(1);
+                self.cursor = Position {
+                    x: x.saturating_sub(1),
+                    y: y.saturating_sub(1),
+                };
+            }
+            'J' => {
+                for row in &mut self.cells {
+                    row.iter_mut().for_each(|cell| *cell = String::from(" "));
+                }
+            }
+            'K' => {
+                let Position { x, y } = self.cursor;
+                if let Some(row) = self.cells.get_mut(y) {
+                    row.iter_mut().skip(x).for_each(|cell| *cell = String::from(" "));
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Backend for VirtualScreen {
+    fn size(&self) -> Size {
+        let state = self.state.borrow();
+        Size {
+            width: state.width as u16,
+            height: state.height.saturating_sub(2) as u16,
+        }
+    }
+
+    fn read_input(&mut self) -> Result<Option<Input>, Error> {
+        self.state
+            .borrow_mut()
+            .inputs
+            .pop_front()
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "no more scripted keys"))
+    }
+
+    fn input_pending(&mut self) -> Result<bool, Error> {
+        Ok(!self.state.borrow().inputs.is_empty())
+    }
+
+    // Cursor moves and clears are applied; colours and other escape
+    // sequences are skipped.
+    fn write(&mut self, text: &str) {
+        let mut state = self.state.borrow_mut();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\r' => state.cursor.x = 0,
+                '\n' => state.cursor.y = state.cursor.y.saturating_add(1),
+                '\x1b' => match chars.next() {
+                    Some('[') => {
+                        let mut params = String::new();
+                        for c in chars.by_ref() {
+                            if ('\x40'..='\x7e').contains(&c) {
+                                state.control(c, &params);
+                                break;
+                            }
+                            params.push(c);
+                        }
+                    }
+                    Some(']') => {
+                        for c in chars.by_ref() {
+                            if c == '\x07' {
+                                break;
+                            }
+                        }
+                    }
+                    _ => (),
+                },
+                c => state.put(c),
+            }
+        }
+    }
+
+    fn flash(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn clear_screen(&mut self) {
+        let mut state = self.state.borrow_mut();
+        for row in &mut state.cells {
+            row.iter_mut().for_each(|cell| *cell = String::from(" "));
+        }
+    }
+
+    fn cursor_pos(&mut self, pos: &Position) {
+        self.state.borrow_mut().cursor = pos.clone();
+    }
+
+    fn cursor_hide(&mut self) {}
+
+    fn cursor_show(&mut self) {}
+
+    fn set_clipboard(&mut self, encoded: &str) {
+        self.state.borrow_mut().clipboard = Some(encoded.to_string());
+    }
+}